@@ -1,5 +1,5 @@
 use eframe::wgpu;
-use rendering4d::App;
+use rendering4d::{App, BUILT_IN_PRIMITIVE_KINDS};
 use std::sync::Arc;
 
 fn main() -> anyhow::Result<()> {
@@ -11,17 +11,23 @@ fn main() -> anyhow::Result<()> {
             wgpu_options: eframe::egui_wgpu::WgpuConfiguration {
                 present_mode: wgpu::PresentMode::AutoNoVsync,
                 power_preference: wgpu::PowerPreference::HighPerformance,
-                device_descriptor: Arc::new(|_| wgpu::DeviceDescriptor {
+                device_descriptor: Arc::new(|adapter| wgpu::DeviceDescriptor {
                     label: Some("Device"),
                     required_features: wgpu::Features::default()
                         | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                    required_limits: wgpu::Limits::default(),
+                    // the denoiser and the texture copy bind more than the default 4 storage textures
+                    required_limits: wgpu::Limits {
+                        max_storage_textures_per_shader_stage: adapter
+                            .limits()
+                            .max_storage_textures_per_shader_stage,
+                        ..Default::default()
+                    },
                 }),
                 ..Default::default()
             },
             ..Default::default()
         },
-        Box::new(|cc| Ok(Box::new(App::new(cc)?))),
+        Box::new(|cc| Ok(Box::new(App::new(cc, BUILT_IN_PRIMITIVE_KINDS.into())?))),
     )?;
     Ok(())
 }
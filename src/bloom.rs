@@ -0,0 +1,328 @@
+use eframe::wgpu;
+use encase::{ShaderSize, ShaderType, UniformBuffer};
+
+/// Every level halves the resolution, so this many levels spread the glow over a large part of the image
+const MAX_LEVELS: usize = 6;
+
+#[derive(ShaderType)]
+struct GpuBloom {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+}
+
+pub(crate) struct BloomSettings {
+    pub(crate) enabled: bool,
+    pub(crate) threshold: f32,
+    pub(crate) knee: f32,
+    pub(crate) intensity: f32,
+    pub(crate) radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.1,
+            radius: 0.75,
+        }
+    }
+}
+
+/// Glow around the bright parts of the image, made by blurring them through a chain of ever smaller
+/// textures and adding them back together on the way up
+pub(crate) struct Bloom {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    level_bind_group_layout: wgpu::BindGroupLayout,
+    sized_bind_groups: SizedBindGroups,
+    prefilter_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    upsample_pipeline: wgpu::ComputePipeline,
+    composite_pipeline: wgpu::ComputePipeline,
+}
+
+/// All the bind groups that have to be recreated when the size of the image changes
+struct SizedBindGroups {
+    level_sizes: Vec<(u32, u32)>,
+    /// From the composited image into the first level
+    prefilter_bind_group: wgpu::BindGroup,
+    /// From every level into the next smaller one
+    downsample_bind_groups: Vec<wgpu::BindGroup>,
+    /// From every level into the next larger one
+    upsample_bind_groups: Vec<wgpu::BindGroup>,
+    /// From the first level into the composited image
+    composite_bind_group: wgpu::BindGroup,
+    composite_display_bind_group: wgpu::BindGroup,
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    label: &str,
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn storage_texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::ReadWrite,
+            format: wgpu::TextureFormat::Rgba32Float,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn create_sized_bind_groups(
+    device: &wgpu::Device,
+    level_bind_group_layout: &wgpu::BindGroupLayout,
+    display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+) -> SizedBindGroups {
+    let mut level_sizes = vec![];
+    let (mut level_width, mut level_height) = (width, height);
+    while level_sizes.len() < MAX_LEVELS && level_width > 1 && level_height > 1 {
+        level_width = level_width.div_ceil(2);
+        level_height = level_height.div_ceil(2);
+        level_sizes.push((level_width, level_height));
+    }
+    // an image this small has nothing to blur, but the first level is still needed
+    if level_sizes.is_empty() {
+        level_sizes.push((1, 1));
+    }
+
+    let composite_texture_view = create_texture(device, "Bloom Composite Texture", width, height);
+    let level_texture_views = level_sizes
+        .iter()
+        .map(|&(width, height)| create_texture(device, "Bloom Level Texture", width, height))
+        .collect::<Vec<_>>();
+
+    let level_bind_group = |label, source, destination| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: level_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(destination),
+                },
+            ],
+        })
+    };
+
+    SizedBindGroups {
+        // the prefilter reads the input from the display texture bind group instead of the source
+        prefilter_bind_group: level_bind_group(
+            "Bloom Prefilter Bind Group",
+            &composite_texture_view,
+            &level_texture_views[0],
+        ),
+        downsample_bind_groups: level_texture_views
+            .windows(2)
+            .map(|views| level_bind_group("Bloom Downsample Bind Group", &views[0], &views[1]))
+            .collect(),
+        upsample_bind_groups: level_texture_views
+            .windows(2)
+            .map(|views| level_bind_group("Bloom Upsample Bind Group", &views[1], &views[0]))
+            .collect(),
+        composite_bind_group: level_bind_group(
+            "Bloom Composite Bind Group",
+            &level_texture_views[0],
+            &composite_texture_view,
+        ),
+        composite_display_bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Composite Display Bind Group"),
+            layout: display_texture_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&composite_texture_view),
+            }],
+        }),
+        level_sizes,
+    }
+}
+
+impl Bloom {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bloom Uniform Buffer"),
+            size: GpuBloom::SHADER_SIZE.get(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(GpuBloom::SHADER_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let level_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bloom Level Bind Group Layout"),
+                entries: &[
+                    storage_texture_layout_entry(0),
+                    storage_texture_layout_entry(1),
+                ],
+            });
+
+        let sized_bind_groups = create_sized_bind_groups(
+            device,
+            &level_bind_group_layout,
+            display_texture_bind_group_layout,
+            1,
+            1,
+        );
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./bloom.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[
+                display_texture_bind_group_layout,
+                &bind_group_layout,
+                &level_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+                compilation_options: Default::default(),
+            })
+        };
+
+        Self {
+            uniform_buffer,
+            bind_group,
+            level_bind_group_layout,
+            sized_bind_groups,
+            prefilter_pipeline: create_pipeline("Bloom Prefilter Pipeline", "prefilter"),
+            downsample_pipeline: create_pipeline("Bloom Downsample Pipeline", "downsample"),
+            upsample_pipeline: create_pipeline("Bloom Upsample Pipeline", "upsample"),
+            composite_pipeline: create_pipeline("Bloom Composite Pipeline", "composite"),
+        }
+    }
+
+    pub(crate) fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) {
+        self.sized_bind_groups = create_sized_bind_groups(
+            device,
+            &self.level_bind_group_layout,
+            display_texture_bind_group_layout,
+            width,
+            height,
+        );
+    }
+
+    /// Uploads the settings for this frame
+    pub(crate) fn prepare(&self, queue: &wgpu::Queue, settings: &BloomSettings) {
+        let mut buffer = UniformBuffer::new([0; GpuBloom::SHADER_SIZE.get() as _]);
+        buffer
+            .write(&GpuBloom {
+                threshold: settings.threshold,
+                knee: settings.knee,
+                intensity: settings.intensity,
+                radius: settings.radius,
+            })
+            .unwrap();
+        queue.write_buffer(&self.uniform_buffer, 0, &buffer.into_inner());
+    }
+
+    /// Adds bloom to the image in `input_bind_group`, returning the display texture bind group of the result
+    pub(crate) fn encode<'a>(
+        &'a self,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+        input_bind_group: &'a wgpu::BindGroup,
+        (width, height): (u32, u32),
+    ) -> &'a wgpu::BindGroup {
+        let SizedBindGroups {
+            level_sizes,
+            prefilter_bind_group,
+            downsample_bind_groups,
+            upsample_bind_groups,
+            composite_bind_group,
+            composite_display_bind_group,
+        } = &self.sized_bind_groups;
+        let dispatch = |compute_pass: &mut wgpu::ComputePass<'a>, (width, height): (u32, u32)| {
+            compute_pass.dispatch_workgroups(width.div_ceil(16), height.div_ceil(16), 1);
+        };
+
+        compute_pass.set_bind_group(0, input_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+
+        compute_pass.set_pipeline(&self.prefilter_pipeline);
+        compute_pass.set_bind_group(2, prefilter_bind_group, &[]);
+        dispatch(compute_pass, level_sizes[0]);
+
+        compute_pass.set_pipeline(&self.downsample_pipeline);
+        for (bind_group, &size) in downsample_bind_groups.iter().zip(&level_sizes[1..]) {
+            compute_pass.set_bind_group(2, bind_group, &[]);
+            dispatch(compute_pass, size);
+        }
+
+        compute_pass.set_pipeline(&self.upsample_pipeline);
+        for (bind_group, &size) in upsample_bind_groups.iter().zip(level_sizes).rev() {
+            compute_pass.set_bind_group(2, bind_group, &[]);
+            dispatch(compute_pass, size);
+        }
+
+        compute_pass.set_pipeline(&self.composite_pipeline);
+        compute_pass.set_bind_group(2, composite_bind_group, &[]);
+        dispatch(compute_pass, (width, height));
+
+        composite_display_bind_group
+    }
+}
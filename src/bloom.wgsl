@@ -0,0 +1,145 @@
+@group(0)
+@binding(0)
+var input_texture: texture_storage_2d<rgba32float, read_write>;
+
+struct Bloom {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    radius: f32,
+}
+
+@group(1)
+@binding(0)
+var<uniform> bloom: Bloom;
+
+@group(2)
+@binding(0)
+var source_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(2)
+@binding(1)
+var destination_texture: texture_storage_2d<rgba32float, read_write>;
+
+fn load_input(coords: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(input_texture));
+    return textureLoad(input_texture, clamp(coords, vec2<i32>(0), size - 1)).rgb;
+}
+
+fn load_source(coords: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(source_texture));
+    return textureLoad(source_texture, clamp(coords, vec2<i32>(0), size - 1)).rgb;
+}
+
+// storage textures cannot be sampled, so this does the bilinear filtering by hand
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    let position = uv * vec2<f32>(textureDimensions(source_texture)) - 0.5;
+    let coords = vec2<i32>(floor(position));
+    let t = fract(position);
+    return mix(
+        mix(load_source(coords), load_source(coords + vec2<i32>(1, 0)), t.x),
+        mix(load_source(coords + vec2<i32>(0, 1)), load_source(coords + vec2<i32>(1, 1)), t.x),
+        t.y,
+    );
+}
+
+// a 3x3 tent filter made of bilinear samples, which smooths out the blockiness of the smaller levels
+fn upsample_source(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    var color = sample_source(uv) * 4.0;
+    color += (sample_source(uv + vec2<f32>(texel.x, 0.0)) + sample_source(uv - vec2<f32>(texel.x, 0.0))) * 2.0;
+    color += (sample_source(uv + vec2<f32>(0.0, texel.y)) + sample_source(uv - vec2<f32>(0.0, texel.y))) * 2.0;
+    color += sample_source(uv + texel) + sample_source(uv - texel);
+    color += sample_source(uv + vec2<f32>(texel.x, -texel.y)) + sample_source(uv + vec2<f32>(-texel.x, texel.y));
+    return color / 16.0;
+}
+
+// only keeps what is brighter than the threshold, with a quadratic curve of width `knee` around it
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    soft = soft * soft / (4.0 * bloom.knee + 0.0001);
+    return color * max(soft, brightness - bloom.threshold) / max(brightness, 0.0001);
+}
+
+// downsamples the input into the first level, keeping only the bright parts
+@compute
+@workgroup_size(16, 16)
+fn prefilter(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(destination_texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let input_coords = vec2<i32>(coords) * 2;
+    let color = (
+        threshold(load_input(input_coords))
+        + threshold(load_input(input_coords + vec2<i32>(1, 0)))
+        + threshold(load_input(input_coords + vec2<i32>(0, 1)))
+        + threshold(load_input(input_coords + vec2<i32>(1, 1)))
+    ) / 4.0;
+    textureStore(destination_texture, coords, vec4<f32>(color, 1.0));
+}
+
+// halves the resolution of the source level into the next one
+@compute
+@workgroup_size(16, 16)
+fn downsample(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(destination_texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let source_coords = vec2<i32>(coords) * 2;
+    let color = (
+        load_source(source_coords)
+        + load_source(source_coords + vec2<i32>(1, 0))
+        + load_source(source_coords + vec2<i32>(0, 1))
+        + load_source(source_coords + vec2<i32>(1, 1))
+    ) / 4.0;
+    textureStore(destination_texture, coords, vec4<f32>(color, 1.0));
+}
+
+// blends the smaller source level into the larger destination level, `radius` decides how far the glow spreads
+@compute
+@workgroup_size(16, 16)
+fn upsample(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(destination_texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(coords) + 0.5) / vec2<f32>(size);
+    let color = mix(textureLoad(destination_texture, coords).rgb, upsample_source(uv), bloom.radius);
+    textureStore(destination_texture, coords, vec4<f32>(color, 1.0));
+}
+
+// adds the glow from the first level on top of the input
+@compute
+@workgroup_size(16, 16)
+fn composite(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(destination_texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let uv = (vec2<f32>(coords) + 0.5) / vec2<f32>(size);
+    let color = load_input(vec2<i32>(coords)) + upsample_source(uv) * bloom.intensity;
+    textureStore(destination_texture, coords, vec4<f32>(color, 1.0));
+}
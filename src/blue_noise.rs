@@ -0,0 +1,123 @@
+//! Blue noise tile generation using the void and cluster method
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIGMA: f32 = 1.5;
+/// The gaussian energy is negligible past this many pixels away
+const RADIUS: isize = 6;
+const INITIAL_DENSITY: f32 = 0.1;
+
+/// Generates a `size` by `size` tileable blue noise texture, every value is a distinct multiple of `1 / (size * size)` in `[0, 1)`
+pub(crate) fn generate_blue_noise(size: usize, seed: u64) -> Vec<f32> {
+    let pixel_count = size * size;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pattern = Pattern::new(size);
+    let initial_count = ((pixel_count as f32 * INITIAL_DENSITY) as usize).max(1);
+    while pattern.count < initial_count {
+        let index = rng.gen_range(0..pixel_count);
+        if !pattern.points[index] {
+            pattern.toggle(index);
+        }
+    }
+
+    // move points out of the tightest clusters into the largest voids until nothing changes
+    for _ in 0..pixel_count {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; pixel_count];
+    {
+        let mut pattern = pattern.clone();
+        while pattern.count > 0 {
+            let cluster = pattern.tightest_cluster();
+            pattern.toggle(cluster);
+            ranks[cluster] = pattern.count;
+        }
+    }
+    while pattern.count < pixel_count {
+        let void = pattern.largest_void();
+        ranks[void] = pattern.count;
+        pattern.toggle(void);
+    }
+
+    ranks
+        .into_iter()
+        .map(|rank| rank as f32 / pixel_count as f32)
+        .collect()
+}
+
+#[derive(Clone)]
+struct Pattern {
+    size: usize,
+    points: Vec<bool>,
+    energy: Vec<f32>,
+    count: usize,
+}
+
+impl Pattern {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            points: vec![false; size * size],
+            energy: vec![0.0; size * size],
+            count: 0,
+        }
+    }
+
+    fn toggle(&mut self, index: usize) {
+        let sign = if self.points[index] { -1.0 } else { 1.0 };
+        self.points[index] = !self.points[index];
+        if self.points[index] {
+            self.count += 1;
+        } else {
+            self.count -= 1;
+        }
+
+        let size = self.size as isize;
+        let (x, y) = (index as isize % size, index as isize / size);
+        for dy in -RADIUS.min(size / 2)..=RADIUS.min((size - 1) / 2) {
+            for dx in -RADIUS.min(size / 2)..=RADIUS.min((size - 1) / 2) {
+                let distance_squared = (dx * dx + dy * dy) as f32;
+                let i = (x + dx).rem_euclid(size) + (y + dy).rem_euclid(size) * size;
+                self.energy[i as usize] +=
+                    sign * f32::exp(-distance_squared / (2.0 * SIGMA * SIGMA));
+            }
+        }
+    }
+
+    fn tightest_cluster(&self) -> usize {
+        (0..self.points.len())
+            .filter(|&i| self.points[i])
+            .max_by(|&a, &b| self.energy[a].total_cmp(&self.energy[b]))
+            .unwrap()
+    }
+
+    fn largest_void(&self) -> usize {
+        (0..self.points.len())
+            .filter(|&i| !self.points[i])
+            .min_by(|&a, &b| self.energy[a].total_cmp(&self.energy[b]))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blue_noise_values_are_a_permutation() {
+        let size = 16;
+        let mut ranks = generate_blue_noise(size, 0)
+            .into_iter()
+            .map(|value| (value * (size * size) as f32).round() as usize)
+            .collect::<Vec<_>>();
+        ranks.sort_unstable();
+        assert_eq!(ranks, (0..size * size).collect::<Vec<_>>());
+    }
+}
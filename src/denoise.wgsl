@@ -0,0 +1,225 @@
+@group(0)
+@binding(0)
+var texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(0)
+@binding(1)
+var normal_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(0)
+@binding(2)
+var albedo_depth_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(0)
+@binding(4)
+var moment_texture: texture_storage_2d<rgba32float, read_write>;
+
+struct Denoiser {
+    position: vec4<f32>,
+    previous_position: vec4<f32>,
+    tan_half_fov: f32,
+    previous_tan_half_fov: f32,
+    has_history: u32,
+    max_history: f32,
+    color_sigma: f32,
+    normal_sigma: f32,
+    depth_sigma: f32,
+    albedo_sigma: f32,
+}
+
+@group(1)
+@binding(0)
+var<uniform> denoiser: Denoiser;
+
+// the reprojected color in rgb and how many samples it is worth in a
+@group(1)
+@binding(1)
+var history_texture: texture_storage_2d<rgba32float, read_write>;
+
+// the color including history in rgb and how many samples it is worth in a
+@group(1)
+@binding(2)
+var integrated_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(1)
+@binding(3)
+var integrated_position_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(2)
+@binding(0)
+var input_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(2)
+@binding(1)
+var output_texture: texture_storage_2d<rgba32float, read_write>;
+
+override step_size: i32 = 1;
+
+// the same ray directions as `raytracing.wgsl` uses, without the jitter
+fn first_hit_position(coords: vec2<u32>, size: vec2<u32>, position: vec4<f32>, tan_half_fov: f32, depth: f32) -> vec4<f32> {
+    let aspect = f32(size.x) / f32(size.y);
+    let uv = vec2<f32>(coords) / vec2<f32>(size);
+    var direction = vec4<f32>(1.0, uv.yx * 2.0 - 1.0, 0.0);
+    direction.y *= tan_half_fov;
+    direction.z *= aspect * tan_half_fov;
+    return position + normalize(direction) * depth;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn kernel_weight(offset: i32) -> f32 {
+    switch abs(offset) {
+        case 0: {
+            return 3.0 / 8.0;
+        }
+        case 1: {
+            return 1.0 / 4.0;
+        }
+        default: {
+            return 1.0 / 16.0;
+        }
+    }
+}
+
+// finds where the first hit of this pixel was on screen last frame, and takes the color from there
+@compute
+@workgroup_size(16, 16)
+fn reproject(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    var history = vec4<f32>(0.0);
+    let depth = textureLoad(albedo_depth_texture, coords).a;
+    if denoiser.has_history != 0u && depth > 0.0 {
+        let position = first_hit_position(coords, size, denoiser.position, denoiser.tan_half_fov, depth);
+        let offset = position - denoiser.previous_position;
+        // camera rays never leave the w slice the camera is in
+        if abs(offset.w) < 0.001 * depth && offset.x > 0.0 {
+            let aspect = f32(size.x) / f32(size.y);
+            let uv = vec2<f32>(
+                offset.z / (offset.x * aspect * denoiser.previous_tan_half_fov),
+                offset.y / (offset.x * denoiser.previous_tan_half_fov),
+            ) * 0.5 + 0.5;
+            let previous_coords = vec2<i32>(round(uv * vec2<f32>(size)));
+            if all(previous_coords >= vec2<i32>(0)) && all(previous_coords < vec2<i32>(size)) {
+                let previous_position = textureLoad(integrated_position_texture, previous_coords);
+                // anything else was hidden last frame
+                if distance(previous_position, position) < 0.02 * depth {
+                    let previous = textureLoad(integrated_texture, previous_coords);
+                    history = vec4<f32>(previous.rgb, min(previous.a, denoiser.max_history));
+                }
+            }
+        }
+    }
+    textureStore(history_texture, coords, history);
+}
+
+// combines the history with the accumulated samples, and divides out the albedo so only the lighting gets blurred
+@compute
+@workgroup_size(16, 16)
+fn integrate(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let history = textureLoad(history_texture, coords);
+    let sample_count = textureLoad(moment_texture, coords).g;
+    let total_count = history.a + sample_count;
+    let color = (history.rgb * history.a + textureLoad(texture, coords).rgb * sample_count) / total_count;
+    textureStore(integrated_texture, coords, vec4<f32>(color, total_count));
+
+    let albedo_depth = textureLoad(albedo_depth_texture, coords);
+    textureStore(
+        integrated_position_texture,
+        coords,
+        first_hit_position(coords, size, denoiser.position, denoiser.tan_half_fov, albedo_depth.a),
+    );
+
+    let albedo = max(albedo_depth.rgb, vec3<f32>(0.01));
+    textureStore(output_texture, coords, vec4<f32>(color / albedo, total_count));
+}
+
+// one level of the edge avoiding a-trous wavelet filter, every level doubles `step_size`
+@compute
+@workgroup_size(16, 16)
+fn atrous(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let center = textureLoad(input_texture, coords);
+    let center_luminance = luminance(center.rgb);
+    let center_normal = textureLoad(normal_texture, coords);
+    let center_albedo_depth = textureLoad(albedo_depth_texture, coords);
+    // the more samples a pixel has, the less it needs to borrow from its neighbours
+    let color_sigma = denoiser.color_sigma / sqrt(max(center.a, 1.0));
+
+    var color = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var y = -2; y <= 2; y += 1) {
+        for (var x = -2; x <= 2; x += 1) {
+            let sample_coords = vec2<i32>(coords) + vec2<i32>(x, y) * step_size;
+            if any(sample_coords < vec2<i32>(0)) || any(sample_coords >= vec2<i32>(size)) {
+                continue;
+            }
+
+            let sample = textureLoad(input_texture, sample_coords);
+            let normal = textureLoad(normal_texture, sample_coords);
+            let albedo_depth = textureLoad(albedo_depth_texture, sample_coords);
+
+            var normal_weight = 1.0;
+            if any(center_normal != vec4<f32>(0.0)) && any(normal != vec4<f32>(0.0)) {
+                normal_weight = pow(max(dot(normalize(center_normal), normalize(normal)), 0.0), denoiser.normal_sigma);
+            } else if any(center_normal != normal) {
+                // sky next to geometry
+                normal_weight = 0.0;
+            }
+            let depth_weight = exp(
+                -abs(center_albedo_depth.a - albedo_depth.a) / (denoiser.depth_sigma * f32(step_size) * max(center_albedo_depth.a, 0.01))
+            );
+            let albedo_weight = exp(-distance(center_albedo_depth.rgb, albedo_depth.rgb) / denoiser.albedo_sigma);
+            let color_weight = exp(-abs(center_luminance - luminance(sample.rgb)) / max(color_sigma, 0.0001));
+
+            let weight = kernel_weight(x) * kernel_weight(y) * normal_weight * depth_weight * albedo_weight * color_weight;
+            color += sample.rgb * weight;
+            total_weight += weight;
+        }
+    }
+
+    textureStore(output_texture, coords, vec4<f32>(color / max(total_weight, 0.0001), center.a));
+}
+
+// multiplies the albedo back in after filtering
+@compute
+@workgroup_size(16, 16)
+fn remodulate(
+    @builtin(global_invocation_id) global_id: vec3<u32>
+) {
+    let size = textureDimensions(texture);
+    let coords = global_id.xy;
+
+    if coords.x >= size.x || coords.y >= size.y {
+        return;
+    }
+
+    let albedo = max(textureLoad(albedo_depth_texture, coords).rgb, vec3<f32>(0.01));
+    textureStore(output_texture, coords, vec4<f32>(textureLoad(input_texture, coords).rgb * albedo, 1.0));
+}
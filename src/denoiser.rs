@@ -0,0 +1,454 @@
+use eframe::wgpu;
+use encase::{ShaderSize, ShaderType, UniformBuffer};
+use std::collections::HashMap;
+
+/// The a-trous filter doubles its step size every iteration, more than this stops finding useful neighbours
+pub(crate) const MAX_ITERATIONS: u32 = 5;
+
+#[derive(ShaderType)]
+struct GpuDenoiser {
+    position: cgmath::Vector4<f32>,
+    previous_position: cgmath::Vector4<f32>,
+    tan_half_fov: f32,
+    previous_tan_half_fov: f32,
+    has_history: u32,
+    max_history: f32,
+    color_sigma: f32,
+    normal_sigma: f32,
+    depth_sigma: f32,
+    albedo_sigma: f32,
+}
+
+pub(crate) struct DenoiserSettings {
+    pub(crate) enabled: bool,
+    pub(crate) temporal: bool,
+    pub(crate) iterations: u32,
+    pub(crate) max_history: f32,
+    pub(crate) color_sigma: f32,
+    pub(crate) normal_sigma: f32,
+    pub(crate) depth_sigma: f32,
+    pub(crate) albedo_sigma: f32,
+}
+
+impl Default for DenoiserSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            temporal: true,
+            iterations: MAX_ITERATIONS,
+            max_history: 16.0,
+            color_sigma: 4.0,
+            normal_sigma: 64.0,
+            depth_sigma: 0.1,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// Edge avoiding a-trous wavelet filter guided by the first hit normal, depth and albedo,
+/// with the previous frame reprojected as history whenever the accumulation restarts
+pub(crate) struct Denoiser {
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    ping_pong_bind_group_layout: wgpu::BindGroupLayout,
+    ping_pong_bind_group: wgpu::BindGroup,
+    pong_ping_bind_group: wgpu::BindGroup,
+    ping_display_bind_group: wgpu::BindGroup,
+    pong_display_bind_group: wgpu::BindGroup,
+    reproject_pipeline: wgpu::ComputePipeline,
+    integrate_pipeline: wgpu::ComputePipeline,
+    atrous_pipelines: Vec<wgpu::ComputePipeline>,
+    remodulate_pipeline: wgpu::ComputePipeline,
+    previous_position: cgmath::Vector4<f32>,
+    previous_tan_half_fov: f32,
+    has_history: bool,
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    label: &str,
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn storage_texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::ReadWrite,
+            format: wgpu::TextureFormat::Rgba32Float,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+/// All the bind groups that have to be recreated when the size of the image changes
+struct SizedBindGroups {
+    bind_group: wgpu::BindGroup,
+    ping_pong_bind_group: wgpu::BindGroup,
+    pong_ping_bind_group: wgpu::BindGroup,
+    ping_display_bind_group: wgpu::BindGroup,
+    pong_display_bind_group: wgpu::BindGroup,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_sized_bind_groups(
+    device: &wgpu::Device,
+    uniform_buffer: &wgpu::Buffer,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    ping_pong_bind_group_layout: &wgpu::BindGroupLayout,
+    display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+) -> SizedBindGroups {
+    let history_texture_view = create_texture(device, "Denoiser History Texture", width, height);
+    let integrated_texture_view =
+        create_texture(device, "Denoiser Integrated Texture", width, height);
+    let integrated_position_texture_view = create_texture(
+        device,
+        "Denoiser Integrated Position Texture",
+        width,
+        height,
+    );
+    let ping_texture_view = create_texture(device, "Denoiser Ping Texture", width, height);
+    let pong_texture_view = create_texture(device, "Denoiser Pong Texture", width, height);
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Denoiser Bind Group"),
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&history_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&integrated_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&integrated_position_texture_view),
+            },
+        ],
+    });
+
+    let ping_pong_bind_group = |label, input, output| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: ping_pong_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(output),
+                },
+            ],
+        })
+    };
+    let display_bind_group = |label, texture_view| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: display_texture_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            }],
+        })
+    };
+
+    SizedBindGroups {
+        bind_group,
+        ping_pong_bind_group: ping_pong_bind_group(
+            "Denoiser Ping Pong Bind Group",
+            &ping_texture_view,
+            &pong_texture_view,
+        ),
+        pong_ping_bind_group: ping_pong_bind_group(
+            "Denoiser Pong Ping Bind Group",
+            &pong_texture_view,
+            &ping_texture_view,
+        ),
+        ping_display_bind_group: display_bind_group(
+            "Denoiser Ping Display Bind Group",
+            &ping_texture_view,
+        ),
+        pong_display_bind_group: display_bind_group(
+            "Denoiser Pong Display Bind Group",
+            &pong_texture_view,
+        ),
+    }
+}
+
+impl Denoiser {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        main_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Denoiser Uniform Buffer"),
+            size: GpuDenoiser::SHADER_SIZE.get(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Denoiser Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(GpuDenoiser::SHADER_SIZE),
+                    },
+                    count: None,
+                },
+                storage_texture_layout_entry(1),
+                storage_texture_layout_entry(2),
+                storage_texture_layout_entry(3),
+            ],
+        });
+        let ping_pong_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Denoiser Ping Pong Bind Group Layout"),
+                entries: &[
+                    storage_texture_layout_entry(0),
+                    storage_texture_layout_entry(1),
+                ],
+            });
+
+        let SizedBindGroups {
+            bind_group,
+            ping_pong_bind_group,
+            pong_ping_bind_group,
+            ping_display_bind_group,
+            pong_display_bind_group,
+        } = create_sized_bind_groups(
+            device,
+            &uniform_buffer,
+            &bind_group_layout,
+            &ping_pong_bind_group_layout,
+            display_texture_bind_group_layout,
+            1,
+            1,
+        );
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("./denoise.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Denoiser Pipeline Layout"),
+            bind_group_layouts: &[
+                main_texture_bind_group_layout,
+                &bind_group_layout,
+                &ping_pong_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point, constants: &HashMap<String, f64>| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants,
+                    ..Default::default()
+                },
+            })
+        };
+        let no_constants = HashMap::new();
+
+        Self {
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            ping_pong_bind_group_layout,
+            ping_pong_bind_group,
+            pong_ping_bind_group,
+            ping_display_bind_group,
+            pong_display_bind_group,
+            reproject_pipeline: create_pipeline(
+                "Denoiser Reproject Pipeline",
+                "reproject",
+                &no_constants,
+            ),
+            integrate_pipeline: create_pipeline(
+                "Denoiser Integrate Pipeline",
+                "integrate",
+                &no_constants,
+            ),
+            atrous_pipelines: (0..MAX_ITERATIONS)
+                .map(|iteration| {
+                    create_pipeline(
+                        "Denoiser A-Trous Pipeline",
+                        "atrous",
+                        &HashMap::from([("step_size".into(), (1 << iteration) as f64)]),
+                    )
+                })
+                .collect(),
+            remodulate_pipeline: create_pipeline(
+                "Denoiser Remodulate Pipeline",
+                "remodulate",
+                &no_constants,
+            ),
+            previous_position: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+            previous_tan_half_fov: 1.0,
+            has_history: false,
+        }
+    }
+
+    pub(crate) fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) {
+        let SizedBindGroups {
+            bind_group,
+            ping_pong_bind_group,
+            pong_ping_bind_group,
+            ping_display_bind_group,
+            pong_display_bind_group,
+        } = create_sized_bind_groups(
+            device,
+            &self.uniform_buffer,
+            &self.bind_group_layout,
+            &self.ping_pong_bind_group_layout,
+            display_texture_bind_group_layout,
+            width,
+            height,
+        );
+        self.bind_group = bind_group;
+        self.ping_pong_bind_group = ping_pong_bind_group;
+        self.pong_ping_bind_group = pong_ping_bind_group;
+        self.ping_display_bind_group = ping_display_bind_group;
+        self.pong_display_bind_group = pong_display_bind_group;
+        self.has_history = false;
+    }
+
+    /// Uploads the settings for this frame, must be called every rendered frame even when the denoiser is disabled
+    pub(crate) fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        settings: &DenoiserSettings,
+        position: cgmath::Vector4<f32>,
+        tan_half_fov: f32,
+    ) {
+        if !settings.enabled {
+            self.has_history = false;
+            return;
+        }
+
+        let mut buffer = UniformBuffer::new([0; GpuDenoiser::SHADER_SIZE.get() as _]);
+        buffer
+            .write(&GpuDenoiser {
+                position,
+                previous_position: self.previous_position,
+                tan_half_fov,
+                previous_tan_half_fov: self.previous_tan_half_fov,
+                has_history: (settings.temporal && self.has_history) as _,
+                max_history: settings.max_history,
+                color_sigma: settings.color_sigma,
+                normal_sigma: settings.normal_sigma,
+                depth_sigma: settings.depth_sigma,
+                albedo_sigma: settings.albedo_sigma,
+            })
+            .unwrap();
+        queue.write_buffer(&self.uniform_buffer, 0, &buffer.into_inner());
+
+        self.previous_position = position;
+        self.previous_tan_half_fov = tan_half_fov;
+        self.has_history = true;
+    }
+
+    /// Denoises the main texture, returning the display texture bind group of the result
+    pub(crate) fn encode<'a>(
+        &'a self,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+        main_texture_bind_group: &'a wgpu::BindGroup,
+        settings: &DenoiserSettings,
+        frame_count: u32,
+        (dispatch_width, dispatch_height): (u32, u32),
+    ) -> &'a wgpu::BindGroup {
+        compute_pass.set_bind_group(0, main_texture_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.bind_group, &[]);
+
+        // the history only changes when the accumulation restarts, until then it is the same every frame
+        if frame_count == 0 {
+            compute_pass.set_pipeline(&self.reproject_pipeline);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+
+        compute_pass.set_pipeline(&self.integrate_pipeline);
+        compute_pass.set_bind_group(2, &self.pong_ping_bind_group, &[]);
+        compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+
+        let mut result_in_ping = true;
+        for pipeline in &self.atrous_pipelines[..settings.iterations.min(MAX_ITERATIONS) as usize] {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(
+                2,
+                if result_in_ping {
+                    &self.ping_pong_bind_group
+                } else {
+                    &self.pong_ping_bind_group
+                },
+                &[],
+            );
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+            result_in_ping = !result_in_ping;
+        }
+
+        compute_pass.set_pipeline(&self.remodulate_pipeline);
+        compute_pass.set_bind_group(
+            2,
+            if result_in_ping {
+                &self.ping_pong_bind_group
+            } else {
+                &self.pong_ping_bind_group
+            },
+            &[],
+        );
+        compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+
+        self.display_bind_group(settings)
+    }
+
+    /// The display texture bind group `encode` last wrote the result to, for showing it again without denoising
+    pub(crate) fn display_bind_group(&self, settings: &DenoiserSettings) -> &wgpu::BindGroup {
+        // every iteration swaps the ping and pong textures, and remodulating swaps them once more
+        if settings.iterations.min(MAX_ITERATIONS).is_multiple_of(2) {
+            &self.pong_display_bind_group
+        } else {
+            &self.ping_display_bind_group
+        }
+    }
+}
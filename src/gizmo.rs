@@ -0,0 +1,321 @@
+//! On-screen handles for moving and rotating the selected object in 4D
+
+use crate::scene::Transform;
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector4};
+use eframe::egui;
+
+const AXIS_COLORS: [egui::Color32; 4] = [
+    egui::Color32::from_rgb(230, 60, 60),
+    egui::Color32::from_rgb(60, 200, 60),
+    egui::Color32::from_rgb(70, 110, 240),
+    egui::Color32::from_rgb(200, 80, 255),
+];
+const AXIS_NAMES: [&str; 4] = ["X", "Y", "Z", "W"];
+/// Every pair of axes spans one of the six planes of rotation in 4D
+const PLANES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+/// How long the axis handles are on screen, in points
+const AXIS_LENGTH: f32 = 80.0;
+/// The radius of the rotation rings on screen, in points
+const RING_RADIUS: f32 = 60.0;
+const RING_SEGMENTS: usize = 48;
+/// How close the pointer has to be to a handle to grab it, in points
+const GRAB_DISTANCE: f32 = 6.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GizmoMode {
+    Translate,
+    Rotate,
+}
+
+impl GizmoMode {
+    pub(crate) const ALL: [GizmoMode; 2] = [GizmoMode::Translate, GizmoMode::Rotate];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            GizmoMode::Translate => "Translate",
+            GizmoMode::Rotate => "Rotate",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Handle {
+    Axis(usize),
+    Plane(usize, usize),
+}
+
+pub(crate) struct Gizmo {
+    pub(crate) mode: GizmoMode,
+    /// The handle being dragged, and for rings the angle the pointer was at last frame
+    dragging: Option<(Handle, f32)>,
+    /// Whether the pointer was on a handle last time the gizmo was shown
+    hovered: bool,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            dragging: None,
+            hovered: false,
+        }
+    }
+}
+
+/// Where the gizmo is on screen. The camera only ever sees x, y and z, so w gets drawn along a
+/// fixed diagonal, like the receding axis of an oblique projection
+struct Projection<'a> {
+    center: Vector4<f32>,
+    screen_center: egui::Pos2,
+    /// Points per world unit at the center
+    scale: f32,
+    project: &'a dyn Fn(Vector4<f32>) -> Option<egui::Pos2>,
+}
+
+impl Projection<'_> {
+    fn w_direction() -> egui::Vec2 {
+        egui::vec2(1.0, -1.0).normalized()
+    }
+
+    fn screen(&self, point: Vector4<f32>) -> Option<egui::Pos2> {
+        let offset = point - self.center;
+        let projected = (self.project)(point - Vector4::unit_w() * offset.w)?;
+        Some(projected + Self::w_direction() * offset.w * self.scale)
+    }
+
+    /// Where `direction` from the center ends up on screen, for a handle that is `length` points long
+    fn handle_end(&self, direction: Vector4<f32>, length: f32) -> Option<egui::Pos2> {
+        self.screen(self.center + direction * (length / self.scale))
+    }
+
+    fn axis(&self, axis: usize) -> Option<egui::Pos2> {
+        self.handle_end(unit(axis), AXIS_LENGTH)
+    }
+
+    fn ring(&self, (a, b): (usize, usize)) -> Option<Vec<egui::Pos2>> {
+        (0..=RING_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                self.handle_end(unit(a) * angle.cos() + unit(b) * angle.sin(), RING_RADIUS)
+            })
+            .collect()
+    }
+
+    /// The angle of `pointer` around the ring in the plane of `a` and `b`
+    fn ring_angle(&self, (a, b): (usize, usize), pointer: egui::Pos2) -> Option<f32> {
+        let u = self.handle_end(unit(a), RING_RADIUS)? - self.screen_center;
+        let v = self.handle_end(unit(b), RING_RADIUS)? - self.screen_center;
+        let offset = pointer - self.screen_center;
+        let determinant = u.x * v.y - u.y * v.x;
+        // a ring seen edge on has no angle to speak of
+        if determinant.abs() < 1.0 {
+            return None;
+        }
+        let alpha = (offset.x * v.y - offset.y * v.x) / determinant;
+        let beta = (u.x * offset.y - u.y * offset.x) / determinant;
+        Some(beta.atan2(alpha))
+    }
+}
+
+fn unit(axis: usize) -> Vector4<f32> {
+    let mut unit = Vector4::new(0.0, 0.0, 0.0, 0.0);
+    unit[axis] = 1.0;
+    unit
+}
+
+fn distance_to_segment(point: egui::Pos2, start: egui::Pos2, end: egui::Pos2) -> f32 {
+    let segment = end - start;
+    let t = ((point - start).dot(segment) / segment.length_sq().max(f32::EPSILON)).clamp(0.0, 1.0);
+    point.distance(start + segment * t)
+}
+
+/// A rotation by `angle` in the plane of `a` and `b`, turning `a` towards `b`
+fn plane_rotation((a, b): (usize, usize), angle: f32) -> Matrix4<f32> {
+    let mut rotation = Matrix4::identity();
+    let (sin, cos) = angle.sin_cos();
+    rotation[a][a] = cos;
+    rotation[a][b] = sin;
+    rotation[b][a] = -sin;
+    rotation[b][b] = cos;
+    rotation
+}
+
+impl Gizmo {
+    /// Draws the handles for an object at `transform` and applies dragging them.
+    /// `project` takes a point to the screen, or gives `None` when it is behind the camera.
+    /// Returns whether the object changed
+    pub(crate) fn show(
+        &mut self,
+        ui: &egui::Ui,
+        response: &egui::Response,
+        project: &dyn Fn(Vector4<f32>) -> Option<egui::Pos2>,
+        transform: &mut Transform,
+    ) -> bool {
+        let Transform { position, rotation } = transform;
+        self.hovered = false;
+        let Some(screen_center) = project(*position) else {
+            self.dragging = None;
+            return false;
+        };
+        // how far a world unit next to the object is on screen, along the camera's up axis
+        let Some(scale) = project(*position + Vector4::unit_y())
+            .map(|up| (up - screen_center).length())
+            .filter(|&scale| scale > f32::EPSILON)
+        else {
+            self.dragging = None;
+            return false;
+        };
+        let projection = Projection {
+            center: *position,
+            screen_center,
+            scale,
+            project,
+        };
+
+        let handle_at = |pointer: egui::Pos2| -> Option<Handle> {
+            match self.mode {
+                GizmoMode::Translate => (0..4)
+                    .filter_map(|axis| {
+                        let end = projection.axis(axis)?;
+                        Some((
+                            Handle::Axis(axis),
+                            distance_to_segment(pointer, screen_center, end),
+                        ))
+                    })
+                    .filter(|&(_, distance)| distance < GRAB_DISTANCE)
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(handle, _)| handle),
+                GizmoMode::Rotate => PLANES
+                    .into_iter()
+                    .filter_map(|plane| {
+                        let ring = projection.ring(plane)?;
+                        let distance = ring
+                            .windows(2)
+                            .map(|segment| distance_to_segment(pointer, segment[0], segment[1]))
+                            .fold(f32::INFINITY, f32::min);
+                        Some((Handle::Plane(plane.0, plane.1), distance))
+                    })
+                    .filter(|&(_, distance)| distance < GRAB_DISTANCE)
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(handle, _)| handle),
+            }
+        };
+
+        if response.drag_started() {
+            self.dragging = response.interact_pointer_pos().and_then(|pointer| {
+                let handle = handle_at(pointer)?;
+                let angle = match handle {
+                    Handle::Axis(_) => 0.0,
+                    Handle::Plane(a, b) => projection.ring_angle((a, b), pointer).unwrap_or(0.0),
+                };
+                Some((handle, angle))
+            });
+        }
+        if !response.dragged() {
+            self.dragging = None;
+        }
+
+        let mut changed = false;
+        if let (Some((handle, last_angle)), Some(pointer)) =
+            (&mut self.dragging, response.interact_pointer_pos())
+        {
+            match *handle {
+                Handle::Axis(axis) => {
+                    if let Some(end) = projection.axis(axis) {
+                        let direction = end - screen_center;
+                        // an axis pointing straight at the camera cannot be dragged along
+                        if direction.length() > 1.0 {
+                            let amount = response.drag_delta().dot(direction)
+                                / direction.length_sq()
+                                * (AXIS_LENGTH / scale);
+                            *position += unit(axis) * amount;
+                            changed = amount != 0.0;
+                        }
+                    }
+                }
+                Handle::Plane(a, b) => {
+                    let angle = match projection.ring_angle((a, b), pointer) {
+                        Some(angle) => angle,
+                        // seen edge on, so the horizontal movement turns it instead
+                        None => *last_angle + response.drag_delta().x * 0.01,
+                    };
+                    let delta = angle - *last_angle;
+                    *last_angle = angle;
+                    if delta != 0.0 {
+                        *rotation = plane_rotation((a, b), delta) * *rotation;
+                        // rotations drift away from being orthonormal as they pile up
+                        let mut columns = [rotation.x, rotation.y, rotation.z, rotation.w];
+                        for i in 0..4 {
+                            for j in 0..i {
+                                columns[i] -= columns[j] * columns[i].dot(columns[j]);
+                            }
+                            columns[i] = columns[i].normalize();
+                        }
+                        *rotation =
+                            Matrix4::from_cols(columns[0], columns[1], columns[2], columns[3]);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let highlighted = self
+            .dragging
+            .map(|(handle, _)| handle)
+            .or_else(|| response.hover_pos().and_then(handle_at));
+        self.hovered = highlighted.is_some();
+        let painter = ui.painter_at(response.rect);
+        match self.mode {
+            GizmoMode::Translate => {
+                for axis in 0..4 {
+                    let Some(end) = projection.axis(axis) else {
+                        continue;
+                    };
+                    let width = if highlighted == Some(Handle::Axis(axis)) {
+                        4.0
+                    } else {
+                        2.0
+                    };
+                    painter.line_segment(
+                        [screen_center, end],
+                        egui::Stroke::new(width, AXIS_COLORS[axis]),
+                    );
+                    painter.circle_filled(end, width + 2.0, AXIS_COLORS[axis]);
+                    painter.text(
+                        end + (end - screen_center).normalized() * 10.0,
+                        egui::Align2::CENTER_CENTER,
+                        AXIS_NAMES[axis],
+                        egui::FontId::proportional(12.0),
+                        AXIS_COLORS[axis],
+                    );
+                }
+            }
+            GizmoMode::Rotate => {
+                for (a, b) in PLANES {
+                    let Some(ring) = projection.ring((a, b)) else {
+                        continue;
+                    };
+                    let width = if highlighted == Some(Handle::Plane(a, b)) {
+                        4.0
+                    } else {
+                        2.0
+                    };
+                    let color = egui::Color32::from_rgb(
+                        ((AXIS_COLORS[a].r() as u16 + AXIS_COLORS[b].r() as u16) / 2) as u8,
+                        ((AXIS_COLORS[a].g() as u16 + AXIS_COLORS[b].g() as u16) / 2) as u8,
+                        ((AXIS_COLORS[a].b() as u16 + AXIS_COLORS[b].b() as u16) / 2) as u8,
+                    );
+                    painter.add(egui::Shape::line(ring, egui::Stroke::new(width, color)));
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Whether the pointer is on a handle, so a click there should not change the selection
+    pub(crate) fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+}
@@ -0,0 +1,74 @@
+/// How many edits are kept before the oldest ones get forgotten
+const MAX_ENTRY_COUNT: usize = 200;
+
+struct Entry<T> {
+    label: String,
+    state: T,
+}
+
+/// Every state something has been in, to step back and forth between with undo and redo.
+/// Undoing and then recording a new state forgets the states that were undone
+pub(crate) struct History<T> {
+    entries: Vec<Entry<T>>,
+    current: usize,
+}
+
+impl<T> History<T> {
+    pub(crate) fn new(state: T) -> Self {
+        Self {
+            entries: vec![Entry {
+                label: "Initial State".into(),
+                state,
+            }],
+            current: 0,
+        }
+    }
+
+    /// The state that was last recorded or stepped to
+    pub(crate) fn state(&self) -> &T {
+        &self.entries[self.current].state
+    }
+
+    pub(crate) fn record(&mut self, label: String, state: T) {
+        self.entries.truncate(self.current + 1);
+        self.entries.push(Entry { label, state });
+        if self.entries.len() > MAX_ENTRY_COUNT {
+            self.entries.remove(0);
+        }
+        self.current = self.entries.len() - 1;
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    /// Steps to the state before the current one
+    pub(crate) fn undo(&mut self) -> Option<&T> {
+        self.can_undo().then(|| self.go_to(self.current - 1))
+    }
+
+    /// Steps to the state after the current one
+    pub(crate) fn redo(&mut self) -> Option<&T> {
+        self.can_redo().then(|| self.go_to(self.current + 1))
+    }
+
+    /// Steps to the state at `index` in `labels`
+    pub(crate) fn go_to(&mut self, index: usize) -> &T {
+        self.current = index.min(self.entries.len() - 1);
+        self.state()
+    }
+
+    /// What each state was recorded for, oldest first
+    pub(crate) fn labels(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.label.as_str())
+    }
+
+    /// The index of the current state in `labels`
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+}
@@ -0,0 +1,234 @@
+//! The one kind of primitive that comes with the renderer
+
+use crate::primitive::{BoundingBox4D, Node, Primitive4D};
+use crate::scene::{identity_rotation, Transform};
+use cgmath::InnerSpace;
+use eframe::egui;
+use encase::ShaderType;
+use serde::{Deserialize, Serialize};
+
+#[derive(ShaderType)]
+pub(crate) struct GpuHyperSphere {
+    position: cgmath::Vector4<f32>,
+    color: cgmath::Vector3<f32>,
+    radius: f32,
+    emissive_color: cgmath::Vector3<f32>,
+    emission_strength: f32,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "HyperSphereFile", into = "HyperSphereFile")]
+pub(crate) struct HyperSphere {
+    pub(crate) node: Node,
+    pub(crate) color: cgmath::Vector3<f32>,
+    pub(crate) radius: f32,
+    pub(crate) emissive_color: cgmath::Vector3<f32>,
+    pub(crate) emission_strength: f32,
+}
+
+/// How a hyper sphere is written in scene files, with the fields of its node next to its own
+#[derive(Serialize, Deserialize)]
+struct HyperSphereFile {
+    name: String,
+    id: usize,
+    #[serde(default)]
+    parent: Option<usize>,
+    position: cgmath::Vector4<f32>,
+    /// A hyper sphere looks the same however it is turned, but the gizmo still keeps track of it
+    #[serde(default = "identity_rotation")]
+    rotation: cgmath::Matrix4<f32>,
+    color: cgmath::Vector3<f32>,
+    radius: f32,
+    emissive_color: cgmath::Vector3<f32>,
+    emission_strength: f32,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    locked: bool,
+}
+
+impl From<HyperSphereFile> for HyperSphere {
+    fn from(file: HyperSphereFile) -> Self {
+        let HyperSphereFile {
+            name,
+            id,
+            parent,
+            position,
+            rotation,
+            color,
+            radius,
+            emissive_color,
+            emission_strength,
+            hidden,
+            locked,
+        } = file;
+        HyperSphere {
+            node: Node {
+                name,
+                id,
+                parent,
+                position,
+                rotation,
+                hidden,
+                locked,
+            },
+            color,
+            radius,
+            emissive_color,
+            emission_strength,
+        }
+    }
+}
+
+impl From<HyperSphere> for HyperSphereFile {
+    fn from(hyper_sphere: HyperSphere) -> Self {
+        let HyperSphere {
+            node:
+                Node {
+                    name,
+                    id,
+                    parent,
+                    position,
+                    rotation,
+                    hidden,
+                    locked,
+                },
+            color,
+            radius,
+            emissive_color,
+            emission_strength,
+        } = hyper_sphere;
+        HyperSphereFile {
+            name,
+            id,
+            parent,
+            position,
+            rotation,
+            color,
+            radius,
+            emissive_color,
+            emission_strength,
+            hidden,
+            locked,
+        }
+    }
+}
+
+impl Primitive4D for HyperSphere {
+    const NAME: &'static str = "hyper_sphere";
+    const LIST_NAME: &'static str = "hyper_spheres";
+    const LABEL: &'static str = "Hyper Sphere";
+    const GPU_TYPE: &'static str = "HyperSphere";
+    const WGSL: &'static str = include_str!("./shaders/hyper_sphere.wgsl");
+    const IS_LIGHT: bool = true;
+
+    type Gpu = GpuHyperSphere;
+
+    fn new(node: Node) -> Self {
+        HyperSphere {
+            node,
+            color: cgmath::vec3(0.9, 0.9, 0.9),
+            radius: 1.0,
+            emissive_color: cgmath::vec3(1.0, 1.0, 1.0),
+            emission_strength: 0.0,
+        }
+    }
+
+    fn node(&self) -> &Node {
+        &self.node
+    }
+
+    fn node_mut(&mut self) -> &mut Node {
+        &mut self.node
+    }
+
+    fn bounding_box(&self, transform: Transform) -> BoundingBox4D {
+        let center = transform.position;
+        let extent = cgmath::vec4(self.radius, self.radius, self.radius, self.radius);
+        BoundingBox4D {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+
+    fn intersect(
+        &self,
+        transform: Transform,
+        origin: cgmath::Vector4<f32>,
+        direction: cgmath::Vector4<f32>,
+    ) -> Option<f32> {
+        let oc = origin - transform.position;
+        let a = direction.dot(direction);
+        let half_b = oc.dot(direction);
+        let c = oc.dot(oc) - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let distance = (-half_b - discriminant.sqrt()) / a;
+        (distance >= 0.001).then_some(distance)
+    }
+
+    fn gpu(&self, transform: Transform) -> GpuHyperSphere {
+        let HyperSphere {
+            node: _,
+            color,
+            radius,
+            emissive_color,
+            emission_strength,
+        } = *self;
+        GpuHyperSphere {
+            position: transform.position,
+            color,
+            radius,
+            emissive_color,
+            emission_strength,
+        }
+    }
+
+    fn properties_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Radius:");
+            changed |= ui
+                .add(egui::DragValue::new(&mut self.radius).speed(0.1))
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Color:");
+            changed |= ui.color_edit_button_rgb(self.color.as_mut()).changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Emissive Color:");
+            changed |= ui
+                .color_edit_button_rgb(self.emissive_color.as_mut())
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Emission Strength:");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut self.emission_strength)
+                        .speed(0.1)
+                        .range(0.0..=f32::INFINITY),
+                )
+                .changed();
+        });
+        changed
+    }
+
+    fn copy_changed_properties(&mut self, before: &Self, edited: &Self) {
+        if edited.radius != before.radius {
+            self.radius = edited.radius;
+        }
+        if edited.color != before.color {
+            self.color = edited.color;
+        }
+        if edited.emissive_color != before.emissive_color {
+            self.emissive_color = edited.emissive_color;
+        }
+        if edited.emission_strength != before.emission_strength {
+            self.emission_strength = edited.emission_strength;
+        }
+    }
+}
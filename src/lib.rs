@@ -1,7 +1,44 @@
 #![deny(rust_2018_idioms, rust_2024_compatibility)]
 
+use anyhow::Context;
+use bloom::{Bloom, BloomSettings};
+use cgmath::InnerSpace;
+use denoiser::{Denoiser, DenoiserSettings};
 use eframe::{egui, egui_wgpu, wgpu};
 use encase::{ArrayLength, ShaderSize, ShaderType, StorageBuffer, UniformBuffer};
+use file_watch::FileWatcher;
+use gizmo::{Gizmo, GizmoMode};
+use history::History;
+use hyper_sphere::HyperSphere;
+use prefab::PrefabLibrary;
+use primitive::DynPrimitive;
+use readback::Readback;
+use scene::{any_group, group_transform, is_inside, Group, NodeId, SceneFile};
+use serde::{Deserialize, Serialize};
+use shader_reload::{Shader, ShaderReload};
+use statistics::Statistics;
+use tiled_render::{TilePipelines, TiledRender, TiledRenderSettings};
+
+mod bloom;
+mod blue_noise;
+mod denoiser;
+mod file_watch;
+mod gizmo;
+mod history;
+mod hyper_sphere;
+mod prefab;
+mod primitive;
+mod readback;
+#[cfg(test)]
+mod sampling;
+mod scene;
+mod shader_composer;
+mod shader_reload;
+mod statistics;
+mod tiled_render;
+
+pub use primitive::{BoundingBox4D, Node, Primitive4D, PrimitiveKind};
+pub use scene::{identity_rotation, Transform};
 
 #[derive(ShaderType)]
 struct GpuCamera {
@@ -10,61 +47,805 @@ struct GpuCamera {
     up_sky_color: cgmath::Vector3<f32>,
     down_sky_color: cgmath::Vector3<f32>,
     bounce_count: u32,
+    russian_roulette_depth: u32,
     sample_count: u32,
+    sampler_kind: u32,
+    seed: u32,
     seed_offset: u32,
     frame_count: u32,
+    preview_mode: u32,
+    ambient_occlusion_distance: f32,
+    adaptive_sampling_threshold: f32,
+    adaptive_sampling_min_sample_count: u32,
+    image_size: cgmath::Vector2<u32>,
+    tile_offset: cgmath::Vector2<u32>,
+}
+
+#[derive(ShaderType)]
+struct GpuView {
+    mode: u32,
+    normal_axes: cgmath::Vector3<u32>,
+    max_depth: f32,
+    w_range: f32,
+    max_bounce_count: f32,
+    max_intersection_test_count: f32,
+    max_sample_count: f32,
 }
 
+/// The kinds of primitive the renderer comes with, more can be registered by passing them to `App::new`
+/// along with these
+pub const BUILT_IN_PRIMITIVE_KINDS: [PrimitiveKind; 1] = [PrimitiveKind::of::<HyperSphere>()];
+
+/// The `max_storage_textures_per_shader_stage` limit the device has to be created with, the
+/// default of 4 is not enough for the denoiser
+pub const MIN_STORAGE_TEXTURES_PER_SHADER_STAGE: u32 = 10;
+
 #[derive(ShaderType)]
-struct GpuHyperSphere {
+struct GpuLight {
+    kind: u32,
     position: cgmath::Vector4<f32>,
+    direction: cgmath::Vector4<f32>,
     color: cgmath::Vector3<f32>,
-    radius: f32,
+    intensity: f32,
 }
 
 #[derive(ShaderType)]
-struct GpuHyperSpheres<'a> {
+struct GpuLights<'a> {
     count: ArrayLength,
     #[size(runtime)]
-    data: &'a [GpuHyperSphere],
+    data: &'a [GpuLight],
 }
 
+#[derive(Clone, PartialEq)]
 struct Camera {
     position: cgmath::Vector4<f32>,
     fov: f32,
     up_sky_color: cgmath::Vector3<f32>,
     down_sky_color: cgmath::Vector3<f32>,
     bounce_count: u32,
+    russian_roulette_depth: u32,
     sample_count: u32,
+    sampler: Sampler,
+    seed: u32,
+    preview: Preview,
+    adaptive_sampling: AdaptiveSampling,
+}
+
+/// Cheap shading shown while the scene is being edited
+#[derive(Clone, PartialEq)]
+struct Preview {
+    enabled: bool,
+    ambient_occlusion: bool,
+    ambient_occlusion_distance: f32,
+    /// How long after the last edit to switch back to path tracing, in seconds
+    delay: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ResolutionMode {
+    Scaled,
+    Fixed,
+}
+
+impl ResolutionMode {
+    const ALL: [ResolutionMode; 2] = [ResolutionMode::Scaled, ResolutionMode::Fixed];
+
+    fn name(self) -> &'static str {
+        match self {
+            ResolutionMode::Scaled => "Scaled",
+            ResolutionMode::Fixed => "Fixed",
+        }
+    }
+}
+
+/// The size of the render textures, which get stretched over the viewport
+struct Resolution {
+    mode: ResolutionMode,
+    /// Of the viewport size, for `ResolutionMode::Scaled`
+    scale: f32,
+    /// For `ResolutionMode::Fixed`, letterboxed into the viewport
+    width: u32,
+    height: u32,
+    /// How the render is filtered when it gets stretched over the viewport
+    filter: wgpu::FilterMode,
+}
+
+impl Resolution {
+    /// The render size for a viewport of `rect`, and where in it the render gets drawn
+    fn layout(&self, rect: egui::Rect, max_dimension: u32) -> (u32, u32, egui::Rect) {
+        match self.mode {
+            ResolutionMode::Scaled => (
+                ((rect.width() * self.scale).round() as u32).min(max_dimension),
+                ((rect.height() * self.scale).round() as u32).min(max_dimension),
+                rect,
+            ),
+            ResolutionMode::Fixed => {
+                let (width, height) = (
+                    self.width.clamp(1, max_dimension),
+                    self.height.clamp(1, max_dimension),
+                );
+                let scale = f32::min(rect.width() / width as f32, rect.height() / height as f32);
+                (
+                    width,
+                    height,
+                    egui::Rect::from_center_size(
+                        rect.center(),
+                        egui::vec2(width as f32, height as f32) * scale,
+                    ),
+                )
+            }
+        }
+    }
+}
+
+/// Stops sampling pixels once the estimated error of their mean is small enough
+#[derive(Clone, PartialEq)]
+struct AdaptiveSampling {
+    enabled: bool,
+    /// The standard error of a pixel relative to its luminance below which it is converged
+    threshold: f32,
+    /// Samples every pixel takes before it can be considered converged, so the variance estimate is usable
+    min_sample_count: u32,
+}
+
+/// When progressive rendering stops accumulating and lets the GPU idle
+struct RenderTarget {
+    limit_sample_count: bool,
+    /// Samples per pixel
+    max_sample_count: u32,
+    limit_time: bool,
+    /// In seconds
+    max_time: f32,
+}
+
+impl RenderTarget {
+    fn is_reached(&self, samples_per_pixel: u32, render_time: std::time::Duration) -> bool {
+        (self.limit_sample_count && samples_per_pixel >= self.max_sample_count)
+            || (self.limit_time && render_time.as_secs_f32() >= self.max_time)
+    }
+}
+
+impl Camera {
+    /// The optional parts of `raytracing.wgsl` these settings use, which it checks for with `#ifdef`
+    fn shader_defines(&self) -> Vec<&'static str> {
+        let mut defines = vec![];
+        defines.extend(self.sampler.shader_define());
+        if self.preview.enabled {
+            defines.push("PREVIEW");
+        }
+        if self.adaptive_sampling.enabled {
+            defines.push("ADAPTIVE_SAMPLING");
+        }
+        defines
+    }
+
+    /// The direction of the ray `raytracing.wgsl` shoots through `uv` of an image with `aspect`,
+    /// without the jitter, with y pointing up
+    fn ray_direction(&self, uv: cgmath::Vector2<f32>, aspect: f32) -> cgmath::Vector4<f32> {
+        let tan_half_fov = f32::tan(self.fov.to_radians() / 2.0);
+        cgmath::vec4(
+            1.0,
+            (uv.y * 2.0 - 1.0) * tan_half_fov,
+            (uv.x * 2.0 - 1.0) * aspect * tan_half_fov,
+            0.0,
+        )
+        .normalize()
+    }
+
+    /// Where `point` ends up in an image with `aspect`, the inverse of `ray_direction`,
+    /// or `None` when it is behind the camera
+    fn project(&self, point: cgmath::Vector4<f32>, aspect: f32) -> Option<cgmath::Vector2<f32>> {
+        let tan_half_fov = f32::tan(self.fov.to_radians() / 2.0);
+        let offset = point - self.position;
+        (offset.x > 0.001).then(|| {
+            cgmath::vec2(
+                (offset.z / (offset.x * aspect * tan_half_fov) + 1.0) / 2.0,
+                (offset.y / (offset.x * tan_half_fov) + 1.0) / 2.0,
+            )
+        })
+    }
+
+    /// Everything the shader needs to render frame `frame_count` of the tile at `tile_offset` in an image
+    /// of `image_size`, every render path goes through this so that they all draw the same random numbers
+    /// for the same seed
+    fn gpu_camera(
+        &self,
+        frame_count: u32,
+        previewing: bool,
+        image_size: cgmath::Vector2<u32>,
+        tile_offset: cgmath::Vector2<u32>,
+    ) -> GpuCamera {
+        let Camera {
+            position,
+            fov,
+            up_sky_color,
+            down_sky_color,
+            bounce_count,
+            russian_roulette_depth,
+            sample_count,
+            sampler,
+            seed,
+            preview:
+                Preview {
+                    enabled: _,
+                    ambient_occlusion,
+                    ambient_occlusion_distance,
+                    delay: _,
+                },
+            adaptive_sampling:
+                AdaptiveSampling {
+                    enabled: adaptive_sampling,
+                    threshold: adaptive_sampling_threshold,
+                    min_sample_count: adaptive_sampling_min_sample_count,
+                },
+        } = *self;
+        GpuCamera {
+            position,
+            tan_half_fov: f32::tan(fov.to_radians() / 2.0),
+            up_sky_color,
+            down_sky_color,
+            bounce_count,
+            russian_roulette_depth,
+            sample_count,
+            sampler_kind: sampler.gpu_kind(),
+            seed,
+            seed_offset: frame_seed(seed, frame_count),
+            frame_count,
+            preview_mode: match (previewing, ambient_occlusion) {
+                (false, _) => 0,
+                (true, false) => 1,
+                (true, true) => 2,
+            },
+            ambient_occlusion_distance,
+            // the preview is only shown for a moment, it is not worth converging
+            adaptive_sampling_threshold: if adaptive_sampling && !previewing {
+                adaptive_sampling_threshold
+            } else {
+                0.0
+            },
+            adaptive_sampling_min_sample_count,
+            image_size,
+            tile_offset,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Sampler {
+    /// Independent white noise for every sample
+    Random,
+    /// Owen scrambled Sobol sequence
+    Sobol,
+    /// Randomly rotated R2 sequence
+    R2,
+    /// Blue noise tile animated over frames
+    BlueNoise,
+}
+
+impl Sampler {
+    const ALL: [Sampler; 4] = [
+        Sampler::Random,
+        Sampler::Sobol,
+        Sampler::R2,
+        Sampler::BlueNoise,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Sampler::Random => "Random",
+            Sampler::Sobol => "Sobol",
+            Sampler::R2 => "R2",
+            Sampler::BlueNoise => "Blue Noise",
+        }
+    }
+
+    /// What the raytracing shader needs defined to compile the sampler in
+    fn shader_define(self) -> Option<&'static str> {
+        match self {
+            Sampler::Random => None,
+            Sampler::Sobol => Some("SAMPLER_SOBOL"),
+            Sampler::R2 => Some("SAMPLER_R2"),
+            Sampler::BlueNoise => Some("SAMPLER_BLUE_NOISE"),
+        }
+    }
+
+    fn gpu_kind(self) -> u32 {
+        match self {
+            Sampler::Random => 0,
+            Sampler::Sobol => 1,
+            Sampler::R2 => 2,
+            Sampler::BlueNoise => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ViewMode {
+    Beauty,
+    Depth,
+    Normal,
+    Albedo,
+    ObjectId,
+    WCoordinate,
+    BounceCount,
+    IntersectionTestCount,
+    SampleCount,
+}
+
+impl ViewMode {
+    const ALL: [ViewMode; 9] = [
+        ViewMode::Beauty,
+        ViewMode::Depth,
+        ViewMode::Normal,
+        ViewMode::Albedo,
+        ViewMode::ObjectId,
+        ViewMode::WCoordinate,
+        ViewMode::BounceCount,
+        ViewMode::IntersectionTestCount,
+        ViewMode::SampleCount,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ViewMode::Beauty => "Beauty",
+            ViewMode::Depth => "Depth",
+            ViewMode::Normal => "Normal",
+            ViewMode::Albedo => "Albedo",
+            ViewMode::ObjectId => "Object ID",
+            ViewMode::WCoordinate => "W Coordinate",
+            ViewMode::BounceCount => "Bounce Count",
+            ViewMode::IntersectionTestCount => "Intersection Test Count",
+            ViewMode::SampleCount => "Sample Count",
+        }
+    }
+
+    fn gpu_mode(self) -> u32 {
+        match self {
+            ViewMode::Beauty => 0,
+            ViewMode::Depth => 1,
+            ViewMode::Normal => 2,
+            ViewMode::Albedo => 3,
+            ViewMode::ObjectId => 4,
+            ViewMode::WCoordinate => 5,
+            ViewMode::BounceCount => 6,
+            ViewMode::IntersectionTestCount => 7,
+            ViewMode::SampleCount => 8,
+        }
+    }
+}
+
+/// What gets shown in the viewport, none of this affects the accumulated image
+struct View {
+    mode: ViewMode,
+    /// Which of the 4 normal components get shown as red, green and blue
+    normal_axes: [usize; 3],
+    max_depth: f32,
+    w_range: f32,
+    max_bounce_count: f32,
+    max_intersection_test_count: f32,
+    max_sample_count: f32,
+    /// Whether hovering the viewport shows what is under the cursor
+    pixel_inspector: bool,
+}
+
+impl View {
+    fn gpu_view(&self) -> GpuView {
+        let View {
+            mode,
+            normal_axes,
+            max_depth,
+            w_range,
+            max_bounce_count,
+            max_intersection_test_count,
+            max_sample_count,
+            pixel_inspector: _,
+        } = *self;
+        GpuView {
+            mode: mode.gpu_mode(),
+            normal_axes: cgmath::Vector3::from(normal_axes.map(|axis| axis as u32)),
+            max_depth,
+            w_range,
+            max_bounce_count,
+            max_intersection_test_count,
+            max_sample_count,
+        }
+    }
 }
 
-struct HyperSphere {
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum LightKind {
+    /// Emits from `position` in every direction, falling off with the cube of the distance
+    Point,
+    /// Emits along `direction` from infinitely far away, like the sun
+    Directional,
+}
+
+impl LightKind {
+    fn gpu_kind(self) -> u32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Light {
     name: String,
     id: usize,
+    kind: LightKind,
     position: cgmath::Vector4<f32>,
+    direction: cgmath::Vector4<f32>,
     color: cgmath::Vector3<f32>,
-    radius: f32,
+    intensity: f32,
+}
+
+/// Everything undo and redo step through
+#[derive(Clone, PartialEq)]
+struct SceneSnapshot {
+    camera: Camera,
+    groups: Vec<Group>,
+    /// One list for every registered kind
+    primitives: Vec<Vec<Box<dyn DynPrimitive>>>,
+    lights: Vec<Light>,
+}
+
+/// What happened to a list of objects between `before` and `after`, for the history panel
+fn object_edit_label<T: PartialEq>(
+    before: &[T],
+    after: &[T],
+    id: impl Fn(&T) -> usize,
+    name: impl Fn(&T) -> &str,
+) -> Option<String> {
+    if let Some(created) = after
+        .iter()
+        .find(|object| !before.iter().any(|other| id(other) == id(object)))
+    {
+        return Some(format!("Create {}", name(created)));
+    }
+    if let Some(deleted) = before
+        .iter()
+        .find(|object| !after.iter().any(|other| id(other) == id(object)))
+    {
+        return Some(format!("Delete {}", name(deleted)));
+    }
+    after
+        .iter()
+        .zip(before)
+        .find(|(after, before)| after != before)
+        .map(|(edited, _)| format!("Edit {}", name(edited)))
+}
+
+impl SceneSnapshot {
+    fn edit_label(&self, after: &SceneSnapshot) -> String {
+        if self.camera != after.camera {
+            return "Edit Camera".into();
+        }
+        object_edit_label(
+            &self.groups,
+            &after.groups,
+            |group| group.id,
+            |group| &group.name,
+        )
+        .or_else(|| {
+            self.primitives
+                .iter()
+                .zip(&after.primitives)
+                .find_map(|(before, after)| {
+                    object_edit_label(
+                        before,
+                        after,
+                        |primitive| primitive.node().id,
+                        |primitive| &primitive.node().name,
+                    )
+                })
+        })
+        .or_else(|| {
+            object_edit_label(
+                &self.lights,
+                &after.lights,
+                |light| light.id,
+                |light| &light.name,
+            )
+        })
+        .unwrap_or_else(|| "Edit".into())
+    }
+}
+
+/// Changes to the scene tree that are made once the tree is done being drawn
+enum SceneTreeAction {
+    /// Replacing the selection, or adding to it with the modifiers
+    Select(NodeId, egui::Modifiers),
+    Delete(NodeId),
+    DeleteSelection,
+    DuplicateSelection,
+    /// Into the group, or to the top level
+    Move(NodeId, Option<usize>),
+    /// Of the kind with the index, into the group or at the top level
+    NewPrimitive(usize, Option<usize>),
+    NewGroup(Option<usize>),
+    /// Saves the contents of the linked prefab instance as the prefab
+    ApplyToPrefab(usize),
 }
 
 pub struct App {
     camera: Camera,
     texture: wgpu::Texture,
     texture_id: egui::TextureId,
+    /// The filter `texture_id` was last registered with
+    texture_filter_mode: wgpu::FilterMode,
     texture_bind_group_layout: wgpu::BindGroupLayout,
     texture_bind_group: wgpu::BindGroup,
     texture_copy_pipeline: wgpu::ComputePipeline,
+    texture_copy_pipeline_layout: wgpu::PipelineLayout,
     main_texture: wgpu::Texture,
+    normal_texture: wgpu::Texture,
+    albedo_depth_texture: wgpu::Texture,
+    aov_texture: wgpu::Texture,
+    moment_texture: wgpu::Texture,
     main_texture_bind_group_layout: wgpu::BindGroupLayout,
     main_texture_bind_group: wgpu::BindGroup,
+    display_texture_bind_group_layout: wgpu::BindGroupLayout,
+    main_display_texture_bind_group: wgpu::BindGroup,
+    view: View,
+    view_uniform_buffer: wgpu::Buffer,
+    view_bind_group_layout: wgpu::BindGroupLayout,
+    view_bind_group: wgpu::BindGroup,
+    denoiser: Denoiser,
+    denoiser_settings: DenoiserSettings,
+    bloom: Bloom,
+    bloom_settings: BloomSettings,
+    statistics: Statistics,
     camera_uniform_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
-    hyper_spheres: Vec<HyperSphere>,
-    hyper_sphere_next_id: usize,
-    hyper_spheres_storage_buffer: wgpu::Buffer,
-    hyper_spheres_bind_group_layout: wgpu::BindGroupLayout,
-    hyper_spheres_bind_group: wgpu::BindGroup,
+    /// The kinds of primitive everything loops over, in the order the shaders count through them
+    primitive_kinds: Vec<PrimitiveKind>,
+    /// One list for every kind in `primitive_kinds`
+    primitives: Vec<Vec<Box<dyn DynPrimitive>>>,
+    primitive_next_id: usize,
+    groups: Vec<Group>,
+    group_next_id: usize,
+    /// One for every kind in `primitive_kinds`
+    primitive_storage_buffers: Vec<wgpu::Buffer>,
+    /// In the order it was selected, the gizmo sits on the last one
+    selection: Vec<NodeId>,
+    /// Where selecting a range with shift starts from
+    selection_anchor: Option<NodeId>,
+    gizmo: Gizmo,
+    /// Whether the last selected node still needs to be opened and scrolled to in the outliner
+    reveal_selected_node: bool,
+    /// Only the nodes whose names contain this are shown in the outliner
+    outliner_filter: String,
+    /// The nodes in the order they are shown in the outliner, for selecting ranges
+    outliner_order: Vec<NodeId>,
+    /// The objects that are on the gpu, by the index the shaders give them
+    uploaded_objects: Vec<NodeId>,
+    /// A flag for every object on the gpu, whether it is selected
+    selected_objects_storage_buffer: wgpu::Buffer,
+    lights: Vec<Light>,
+    light_next_id: usize,
+    lights_storage_buffer: wgpu::Buffer,
+    history: History<SceneSnapshot>,
+    /// Where the scene is opened from and saved to
+    scene_path: String,
+    /// Set while the opened scene file gets reloaded whenever it changes on disk
+    scene_watcher: Option<FileWatcher>,
+    /// The outcome of the last time the scene was opened or saved
+    scene_status: Option<String>,
+    prefab_library: PrefabLibrary,
+    /// What the selection gets saved as
+    prefab_name: String,
+    /// Whether new prefab instances are linked to their prefab
+    prefab_linked: bool,
+    /// The outcome of the last thing done with the prefab library
+    prefab_status: Option<String>,
+    scene_bind_group_layout: wgpu::BindGroupLayout,
+    scene_bind_group: wgpu::BindGroup,
     raytracing_pipeline: wgpu::ComputePipeline,
+    raytracing_pipeline_layout: wgpu::PipelineLayout,
+    shader_reload: ShaderReload,
+    /// The features the raytracing shader was last compiled with
+    raytracing_defines: Vec<&'static str>,
+    resolution: Resolution,
+    tiled_render_settings: TiledRenderSettings,
+    tiled_render: Option<TiledRender>,
+    /// The outcome of the last tiled render, shown until the next one starts
+    tiled_render_status: Option<String>,
     frame_count: u32,
+    last_edit: Option<std::time::Instant>,
+    previewing: bool,
+    render_target: RenderTarget,
+    /// When the current accumulation started
+    render_start: std::time::Instant,
+    /// How long the current accumulation took, stops counting once the render target is reached
+    render_time: std::time::Duration,
+    /// What the pixel inspector shows, which stays from the pixel hovered before until the next one
+    /// is read back
+    inspected_texels: Option<(InspectedPixel, anyhow::Result<[cgmath::Vector4<f32>; 4]>)>,
+    texel_readback: Option<(InspectedPixel, Readback)>,
+}
+
+/// The pixel under the cursor, when the accumulation it is from started and how many frames it had,
+/// the pixel inspector only reads it back again when any of them changes
+type InspectedPixel = (u32, u32, std::time::Instant, u32);
+
+/// Copies the texel at `x`, `y` out of every one of `textures`, which need `COPY_SRC` usage and a four
+/// component float format. `texels_from_bytes` turns what gets read back into the texels
+fn read_texels<const N: usize>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    textures: [&wgpu::Texture; N],
+    x: u32,
+    y: u32,
+) -> Readback {
+    Readback::new(device, queue, TEXEL_SIZE * N as u64, |encoder, buffer| {
+        for (i, texture) in textures.iter().enumerate() {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: TEXEL_SIZE * i as u64,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    })
+}
+
+const TEXEL_SIZE: u64 = 16;
+
+fn texels_from_bytes<const N: usize>(data: &[u8]) -> [cgmath::Vector4<f32>; N] {
+    let mut components = data
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes(bytes.try_into().unwrap()));
+    std::array::from_fn(|_| {
+        cgmath::vec4(
+            components.next().unwrap(),
+            components.next().unwrap(),
+            components.next().unwrap(),
+            components.next().unwrap(),
+        )
+    })
+}
+
+/// The same integer hash `raytracing.wgsl` uses
+fn hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let result = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (result >> 22) ^ result
+}
+
+/// The seed for the random numbers of a single frame, so renders with the same seed match exactly
+fn frame_seed(seed: u32, frame_count: u32) -> u32 {
+    hash(seed ^ hash(frame_count))
+}
+
+/// The line a node has in the scene tree, with a handle to drag it into another group.
+/// Returns whether it got hidden or shown
+fn node_header_ui(
+    ui: &mut egui::Ui,
+    node: NodeId,
+    name: &str,
+    hidden: &mut bool,
+    locked: &mut bool,
+    selected: bool,
+    actions: &mut Vec<SceneTreeAction>,
+) -> bool {
+    ui.dnd_drag_source(egui::Id::new(("Scene Tree Drag", node)), node, |ui| {
+        ui.label("☰");
+    })
+    .response
+    .on_hover_text("Drag onto a group to move it there");
+    let mut visible = !*hidden;
+    let visibility_changed = ui
+        .toggle_value(&mut visible, "👁")
+        .on_hover_text("Visible")
+        .changed();
+    *hidden = !visible;
+    ui.toggle_value(locked, "🔒").on_hover_text("Locked");
+    let response = ui.selectable_label(selected, name);
+    if response.clicked() {
+        actions.push(SceneTreeAction::Select(
+            node,
+            ui.input(|input| input.modifiers),
+        ));
+    }
+    visibility_changed
+}
+
+/// The properties of a group, returns whether any of them changed
+fn group_ui(ui: &mut egui::Ui, group: &mut Group) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Name:");
+        ui.text_edit_singleline(&mut group.name);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Position:");
+        changed |= vec4_ui(ui, &mut group.position);
+    });
+    if ui.button("Reset Rotation").clicked() {
+        group.rotation = cgmath::SquareMatrix::identity();
+        changed = true;
+    }
+    changed
+}
+
+/// The properties of a primitive, returns whether any of them changed
+fn primitive_ui(ui: &mut egui::Ui, primitive: &mut dyn DynPrimitive) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Name:");
+        ui.text_edit_singleline(&mut primitive.node_mut().name);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Position:");
+        changed |= vec4_ui(ui, &mut primitive.node_mut().position);
+    });
+    changed |= primitive.properties_ui(ui);
+    changed
+}
+
+fn create_view_bind_group(
+    device: &wgpu::Device,
+    view_bind_group_layout: &wgpu::BindGroupLayout,
+    view_uniform_buffer: &wgpu::Buffer,
+    selected_objects_storage_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("View Bind Group"),
+        layout: view_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: view_uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: selected_objects_storage_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+fn create_scene_bind_group(
+    device: &wgpu::Device,
+    scene_bind_group_layout: &wgpu::BindGroupLayout,
+    lights_storage_buffer: &wgpu::Buffer,
+    primitive_storage_buffers: &[wgpu::Buffer],
+) -> wgpu::BindGroup {
+    let entries = std::iter::once(wgpu::BindGroupEntry {
+        binding: 0,
+        resource: lights_storage_buffer.as_entire_binding(),
+    })
+    .chain(
+        primitive_storage_buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| wgpu::BindGroupEntry {
+                binding: PrimitiveKind::binding(index),
+                resource: buffer.as_entire_binding(),
+            }),
+    )
+    .collect::<Vec<_>>();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Scene Bind Group"),
+        layout: scene_bind_group_layout,
+        entries: &entries,
+    })
 }
 
 fn vec4_ui(ui: &mut egui::Ui, value: &mut cgmath::Vector4<f32>) -> bool {
@@ -85,10 +866,42 @@ fn vec4_ui(ui: &mut egui::Ui, value: &mut cgmath::Vector4<f32>) -> bool {
 }
 
 impl App {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> anyhow::Result<Self> {
+    /// `primitive_kinds` are the kinds of primitive the scene can have, `BUILT_IN_PRIMITIVE_KINDS`
+    /// and any others
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        primitive_kinds: Vec<PrimitiveKind>,
+    ) -> anyhow::Result<Self> {
         let egui_wgpu::RenderState {
-            device, renderer, ..
-        } = cc.wgpu_render_state.as_ref().unwrap();
+            device,
+            queue,
+            renderer,
+            ..
+        } = cc
+            .wgpu_render_state
+            .as_ref()
+            .context("the app needs eframe to use the wgpu renderer")?;
+        for (index, kind) in primitive_kinds.iter().enumerate() {
+            // the scene files and the shaders find the kinds by these names
+            anyhow::ensure!(
+                !["groups", "lights"].contains(&kind.list_name)
+                    && !primitive_kinds[..index].iter().any(|other| {
+                        other.name == kind.name || other.list_name == kind.list_name
+                    }),
+                "there is more than one kind of primitive called {} or {}",
+                kind.name,
+                kind.list_name,
+            );
+        }
+        // the denoiser binds the main textures, its own and the ping pong pair all at once
+        let max_storage_textures_per_shader_stage =
+            device.limits().max_storage_textures_per_shader_stage;
+        anyhow::ensure!(
+            max_storage_textures_per_shader_stage >= MIN_STORAGE_TEXTURES_PER_SHADER_STAGE,
+            "the device was created with a max_storage_textures_per_shader_stage limit of \
+             {max_storage_textures_per_shader_stage}, but the app needs at least \
+             {MIN_STORAGE_TEXTURES_PER_SHADER_STAGE}",
+        );
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Texture"),
@@ -144,16 +957,64 @@ impl App {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            // copied from for the pixel inspector
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
         let main_texture_view = main_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let normal_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Normal Texture"),
+            size: main_texture.size(),
+            mip_level_count: main_texture.mip_level_count(),
+            sample_count: main_texture.sample_count(),
+            dimension: main_texture.dimension(),
+            format: main_texture.format(),
+            usage: main_texture.usage(),
+            view_formats: &[],
+        });
+        let normal_texture_view =
+            normal_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let albedo_depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Albedo Depth Texture"),
+            size: main_texture.size(),
+            mip_level_count: main_texture.mip_level_count(),
+            sample_count: main_texture.sample_count(),
+            dimension: main_texture.dimension(),
+            format: main_texture.format(),
+            usage: main_texture.usage(),
+            view_formats: &[],
+        });
+        let albedo_depth_texture_view =
+            albedo_depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let aov_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("AOV Texture"),
+            size: main_texture.size(),
+            mip_level_count: main_texture.mip_level_count(),
+            sample_count: main_texture.sample_count(),
+            dimension: main_texture.dimension(),
+            format: main_texture.format(),
+            usage: main_texture.usage(),
+            view_formats: &[],
+        });
+        let aov_texture_view = aov_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let moment_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Moment Texture"),
+            size: main_texture.size(),
+            mip_level_count: main_texture.mip_level_count(),
+            sample_count: main_texture.sample_count(),
+            dimension: main_texture.dimension(),
+            format: main_texture.format(),
+            usage: main_texture.usage(),
+            view_formats: &[],
+        });
+        let moment_texture_view =
+            moment_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let main_texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Main Texture Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
+                entries: &[0, 1, 2, 3, 4].map(|binding| wgpu::BindGroupLayoutEntry {
+                    binding,
                     visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::StorageTexture {
                         access: wgpu::StorageTextureAccess::ReadWrite,
@@ -161,33 +1022,134 @@ impl App {
                         view_dimension: wgpu::TextureViewDimension::D2,
                     },
                     count: None,
-                }],
+                }),
             });
         let main_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Main Texture Bind Group"),
             layout: &main_texture_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&main_texture_view),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&main_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&albedo_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&aov_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&moment_texture_view),
+                },
+            ],
+        });
+
+        let display_texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Display Texture Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadWrite,
+                        format: main_texture.format(),
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                }],
+            });
+        let main_display_texture_bind_group =
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Main Display Texture Bind Group"),
+                layout: &display_texture_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&main_texture_view),
+                }],
+            });
+
+        let denoiser = Denoiser::new(
+            device,
+            &main_texture_bind_group_layout,
+            &display_texture_bind_group_layout,
+        );
+        let bloom = Bloom::new(device, &display_texture_bind_group_layout);
+
+        let view_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("View Uniform Buffer"),
+            size: GpuView::SHADER_SIZE.get(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let view_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("View Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(GpuView::SHADER_SIZE),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(u32::SHADER_SIZE),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let selected_objects_storage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Selected Objects Storage Buffer"),
+            size: u32::SHADER_SIZE.get(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
         });
+        let view_bind_group = create_view_bind_group(
+            device,
+            &view_bind_group_layout,
+            &view_uniform_buffer,
+            &selected_objects_storage_buffer,
+        );
 
-        let texture_copy_shader =
-            device.create_shader_module(wgpu::include_wgsl!("./texture_copy.wgsl"));
         let texture_copy_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Texture Copy Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &main_texture_bind_group_layout],
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    &display_texture_bind_group_layout,
+                    &main_texture_bind_group_layout,
+                    &view_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
-        let texture_copy_pipeline =
-            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Texture Copy Pipeline"),
-                layout: Some(&texture_copy_pipeline_layout),
-                module: &texture_copy_shader,
-                entry_point: "main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            });
+        let shader_reload = ShaderReload::new(primitive_kinds.clone());
+        let texture_copy_pipeline = shader_reload::create_pipeline(
+            device,
+            Shader::TextureCopy,
+            &shader_reload
+                .source(Shader::TextureCopy, &[])
+                .map_err(anyhow::Error::msg)?,
+            &texture_copy_pipeline_layout,
+        )
+        .map_err(anyhow::Error::msg)?;
+        let statistics = Statistics::new(device, &main_texture_bind_group_layout, &shader_reload)
+            .map_err(anyhow::Error::msg)?;
 
         let camera_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Camera Uniform Buffer"),
@@ -195,142 +1157,1234 @@ impl App {
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
             mapped_at_creation: false,
         });
+        let blue_noise_size = 64;
+        let blue_noise_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Blue Noise Texture"),
+            size: wgpu::Extent3d {
+                width: blue_noise_size,
+                height: blue_noise_size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            blue_noise_texture.as_image_copy(),
+            &blue_noise::generate_blue_noise(blue_noise_size as _, 0)
+                .into_iter()
+                .flat_map(f32::to_ne_bytes)
+                .collect::<Vec<_>>(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(blue_noise_size * 4),
+                rows_per_image: None,
+            },
+            blue_noise_texture.size(),
+        );
+        let blue_noise_texture_view =
+            blue_noise_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Camera Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(GpuCamera::SHADER_SIZE),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(GpuCamera::SHADER_SIZE),
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
             });
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Camera Bind Group"),
             layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_uniform_buffer.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&blue_noise_texture_view),
+                },
+            ],
         });
 
-        let hyper_spheres_storage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Hyper Spheres Storage Buffer"),
-            size: GpuHyperSpheres::min_size().get(),
+        let primitive_storage_buffers = primitive_kinds
+            .iter()
+            .map(|kind| kind.create_storage_buffer(device))
+            .collect::<Vec<_>>();
+        let lights_storage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lights Storage Buffer"),
+            size: GpuLights::min_size().get(),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
         });
-        let hyper_spheres_bind_group_layout =
+
+        let scene_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Hyper Spheres Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
+                label: Some("Scene Bind Group Layout"),
+                entries: &std::iter::once(wgpu::BindGroupLayoutEntry {
                     binding: 0,
                     visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: false,
-                        min_binding_size: Some(GpuHyperSpheres::min_size()),
+                        min_binding_size: Some(GpuLights::min_size()),
                     },
                     count: None,
-                }],
+                })
+                .chain(
+                    primitive_kinds
+                        .iter()
+                        .enumerate()
+                        .map(|(index, kind)| kind.bind_group_layout_entry(index)),
+                )
+                .collect::<Vec<_>>(),
             });
-        let hyper_spheres_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Hyper Spheres Bind Group"),
-            layout: &hyper_spheres_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: hyper_spheres_storage_buffer.as_entire_binding(),
-            }],
-        });
+        let scene_bind_group = create_scene_bind_group(
+            device,
+            &scene_bind_group_layout,
+            &lights_storage_buffer,
+            &primitive_storage_buffers,
+        );
 
-        let raytracing_shader =
-            device.create_shader_module(wgpu::include_wgsl!("./raytracing.wgsl"));
         let raytracing_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Raytracing Pipeline Layout"),
                 bind_group_layouts: &[
                     &main_texture_bind_group_layout,
                     &camera_bind_group_layout,
-                    &hyper_spheres_bind_group_layout,
+                    &scene_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
-        let raytracing_pipeline =
-            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: Some("Raytracing Pipeline"),
-                layout: Some(&raytracing_pipeline_layout),
-                module: &raytracing_shader,
-                entry_point: "main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            });
+        let camera = Camera {
+            position: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+            fov: 90.0,
+            up_sky_color: cgmath::vec3(0.7, 0.7, 1.0),
+            down_sky_color: cgmath::vec3(0.2, 0.2, 0.2),
+            bounce_count: 4,
+            russian_roulette_depth: 3,
+            sample_count: 1,
+            sampler: Sampler::Sobol,
+            seed: 0,
+            preview: Preview {
+                enabled: true,
+                ambient_occlusion: false,
+                ambient_occlusion_distance: 1.0,
+                delay: 0.5,
+            },
+            adaptive_sampling: AdaptiveSampling {
+                enabled: false,
+                threshold: 0.01,
+                min_sample_count: 16,
+            },
+        };
+        let raytracing_defines = camera.shader_defines();
+        let raytracing_pipeline = shader_reload::create_pipeline(
+            device,
+            Shader::Raytracing,
+            &shader_reload
+                .source(Shader::Raytracing, &raytracing_defines)
+                .map_err(anyhow::Error::msg)?,
+            &raytracing_pipeline_layout,
+        )
+        .map_err(anyhow::Error::msg)?;
+        let mut primitives = vec![vec![]; primitive_kinds.len()];
+        // the default scene only has something in it when the built in kinds are registered
+        if let Some(index) = primitive_kinds
+            .iter()
+            .position(|kind| kind.name == HyperSphere::NAME)
+        {
+            primitives[index].push(Box::new(HyperSphere {
+                color: cgmath::vec3(0.9, 0.1, 0.1),
+                ..HyperSphere::new(Node {
+                    name: "Default Hyper Sphere".into(),
+                    id: 0,
+                    parent: None,
+                    position: cgmath::vec4(2.0, 0.0, 0.0, 0.0),
+                    rotation: cgmath::SquareMatrix::identity(),
+                    hidden: false,
+                    locked: false,
+                })
+            }) as Box<dyn DynPrimitive>);
+        }
+        let lights: Vec<Light> = vec![];
+        let mut prefab_library = PrefabLibrary::default();
+        let prefab_status = prefab_library
+            .reload(&primitive_kinds)
+            .err()
+            .map(|error| format!("Error: {error}"));
+        let history = History::new(SceneSnapshot {
+            camera: camera.clone(),
+            groups: vec![],
+            primitives: primitives.clone(),
+            lights: lights.clone(),
+        });
 
         Ok(Self {
-            camera: Camera {
-                position: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
-                fov: 90.0,
-                up_sky_color: cgmath::vec3(0.7, 0.7, 1.0),
-                down_sky_color: cgmath::vec3(0.2, 0.2, 0.2),
-                bounce_count: 4,
-                sample_count: 1,
-            },
+            camera,
             texture,
             texture_id,
+            texture_filter_mode: wgpu::FilterMode::Nearest,
             texture_bind_group_layout,
             texture_bind_group,
             texture_copy_pipeline,
+            texture_copy_pipeline_layout,
             main_texture,
+            normal_texture,
+            albedo_depth_texture,
+            aov_texture,
+            moment_texture,
             main_texture_bind_group_layout,
             main_texture_bind_group,
+            display_texture_bind_group_layout,
+            main_display_texture_bind_group,
+            view: View {
+                mode: ViewMode::Beauty,
+                normal_axes: [0, 1, 2],
+                max_depth: 10.0,
+                w_range: 2.0,
+                max_bounce_count: 4.0,
+                max_intersection_test_count: 32.0,
+                max_sample_count: 256.0,
+                pixel_inspector: true,
+            },
+            view_uniform_buffer,
+            view_bind_group_layout,
+            view_bind_group,
+            denoiser,
+            denoiser_settings: DenoiserSettings::default(),
+            bloom,
+            bloom_settings: BloomSettings::default(),
+            statistics,
             camera_uniform_buffer,
             camera_bind_group,
-            hyper_spheres: vec![HyperSphere {
-                name: "Default Hyper Sphere".into(),
-                id: 0,
-                position: cgmath::vec4(2.0, 0.0, 0.0, 0.0),
-                color: cgmath::vec3(0.9, 0.1, 0.1),
-                radius: 1.0,
-            }],
-            hyper_sphere_next_id: 1,
-            hyper_spheres_storage_buffer,
-            hyper_spheres_bind_group_layout,
-            hyper_spheres_bind_group,
+            primitive_kinds,
+            primitives,
+            primitive_next_id: 1,
+            groups: vec![],
+            group_next_id: 0,
+            primitive_storage_buffers,
+            selection: vec![],
+            selection_anchor: None,
+            gizmo: Gizmo::default(),
+            reveal_selected_node: false,
+            outliner_filter: String::new(),
+            outliner_order: vec![],
+            uploaded_objects: vec![],
+            selected_objects_storage_buffer,
+            lights,
+            light_next_id: 0,
+            lights_storage_buffer,
+            history,
+            scene_path: "scene.ron".into(),
+            scene_watcher: None,
+            scene_status: None,
+            prefab_library,
+            prefab_name: String::new(),
+            prefab_linked: true,
+            prefab_status,
+            scene_bind_group_layout,
+            scene_bind_group,
             raytracing_pipeline,
+            raytracing_pipeline_layout,
+            shader_reload,
+            raytracing_defines,
+            resolution: Resolution {
+                mode: ResolutionMode::Scaled,
+                scale: 1.0,
+                width: 1920,
+                height: 1080,
+                filter: wgpu::FilterMode::Nearest,
+            },
+            tiled_render_settings: TiledRenderSettings::default(),
+            tiled_render: None,
+            tiled_render_status: None,
             frame_count: 0,
+            last_edit: None,
+            previewing: false,
+            render_target: RenderTarget {
+                limit_sample_count: true,
+                max_sample_count: 4096,
+                limit_time: false,
+                max_time: 60.0,
+            },
+            render_start: std::time::Instant::now(),
+            render_time: std::time::Duration::ZERO,
+            inspected_texels: None,
+            texel_readback: None,
         })
     }
-}
 
-impl eframe::App for App {
-    fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
-        egui::Window::new("Camera").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.label("Position:");
-                if vec4_ui(ui, &mut self.camera.position) {
-                    self.frame_count = 0;
+    /// Puts the scene back to a state from the history
+    fn restore(&mut self, snapshot: Option<SceneSnapshot>) {
+        let Some(SceneSnapshot {
+            camera,
+            groups,
+            primitives,
+            lights,
+        }) = snapshot
+        else {
+            return;
+        };
+        self.camera = camera;
+        self.groups = groups;
+        self.primitives = primitives;
+        self.lights = lights;
+        self.retain_existing_selection();
+        self.frame_count = 0;
+    }
+
+    /// Drops what is not in the scene anymore from the selection, once the scene got replaced
+    fn retain_existing_selection(&mut self) {
+        let selection = std::mem::take(&mut self.selection);
+        let selected_count = selection.len();
+        self.selection = selection
+            .into_iter()
+            .filter(|&node| self.node_transform(node).is_some())
+            .collect();
+        if self
+            .selection_anchor
+            .is_some_and(|anchor| self.node_transform(anchor).is_none())
+        {
+            self.selection_anchor = None;
+        }
+        // the node the outliner was about to reveal may be gone, the one selected last now takes its place
+        if self.selection.len() != selected_count {
+            self.reveal_selected_node = !self.selection.is_empty();
+        }
+    }
+
+    /// Replaces the scene with what is in `file`, keeping its ids so the selection stays on the same
+    /// objects when a file gets reloaded. The camera stays where it is
+    fn load_scene(&mut self, file: SceneFile) {
+        let SceneFile {
+            groups,
+            primitives,
+            lights,
+        } = file;
+        // fresh ids stay past the loaded ones, and past the ones in the history too
+        self.group_next_id = groups
+            .iter()
+            .map(|group| group.id + 1)
+            .fold(self.group_next_id, usize::max);
+        self.primitive_next_id = primitives
+            .iter()
+            .flatten()
+            .map(|primitive| primitive.node().id + 1)
+            .fold(self.primitive_next_id, usize::max);
+        self.light_next_id = lights
+            .iter()
+            .map(|light| light.id + 1)
+            .fold(self.light_next_id, usize::max);
+        self.groups = groups;
+        self.primitives = primitives;
+        self.lights = lights;
+        self.retain_existing_selection();
+        self.frame_count = 0;
+    }
+
+    fn open_scene(&mut self, watch: bool) -> anyhow::Result<()> {
+        let path = std::path::PathBuf::from(&self.scene_path);
+        self.load_scene(SceneFile::load(&path, &self.primitive_kinds)?);
+        self.scene_watcher = watch.then(|| FileWatcher::new(path));
+        Ok(())
+    }
+
+    fn save_scene(&mut self) -> anyhow::Result<()> {
+        let path = std::path::Path::new(&self.scene_path);
+        SceneFile {
+            groups: self.groups.clone(),
+            primitives: self.primitives.clone(),
+            lights: self.lights.clone(),
+        }
+        .save(path, &self.primitive_kinds)?;
+        // starting over, so saving does not count as a change to reload
+        if self.scene_watcher.is_some() {
+            self.scene_watcher = Some(FileWatcher::new(path));
+        }
+        Ok(())
+    }
+
+    /// Compiles the shaders again from wherever `shader_reload` takes them from. The ones that do not
+    /// compile keep their pipelines from before
+    fn reload_shaders(&mut self, device: &wgpu::Device, shaders: &[Shader]) {
+        for &shader in shaders {
+            let layout = match shader {
+                Shader::Raytracing => &self.raytracing_pipeline_layout,
+                Shader::TextureCopy => &self.texture_copy_pipeline_layout,
+                Shader::Statistics => &self.statistics.pipeline_layout,
+            };
+            let defines = match shader {
+                Shader::Raytracing => {
+                    // kept even when it fails to compile, so it is not tried again every frame
+                    self.raytracing_defines = self.camera.shader_defines();
+                    self.raytracing_defines.clone()
                 }
-            });
-            ui.horizontal(|ui| {
-                ui.label("Fov:");
-                if ui
-                    .add(
-                        egui::DragValue::new(&mut self.camera.fov)
-                            .speed(0.1)
-                            .range(1.0..=179.0),
-                    )
-                    .changed()
-                {
+                Shader::TextureCopy | Shader::Statistics => vec![],
+            };
+            let result = self
+                .shader_reload
+                .source(shader, &defines)
+                .and_then(|source| shader_reload::create_pipeline(device, shader, &source, layout));
+            self.shader_reload
+                .errors
+                .retain(|(other, _)| *other != shader);
+            match result {
+                Ok(pipeline) => {
+                    match shader {
+                        Shader::Raytracing => self.raytracing_pipeline = pipeline,
+                        Shader::TextureCopy => self.texture_copy_pipeline = pipeline,
+                        Shader::Statistics => self.statistics.pipeline = pipeline,
+                    }
                     self.frame_count = 0;
                 }
-            });
-            ui.horizontal(|ui| {
-                ui.label("Up Sky Color:");
-                if ui
-                    .color_edit_button_rgb(self.camera.up_sky_color.as_mut())
-                    .changed()
+                Err(error) => self.shader_reload.errors.push((shader, error)),
+            }
+        }
+    }
+
+    /// Deletes the primitives whose nodes `keep` returns false for
+    fn retain_primitives(&mut self, keep: impl Fn(&Node) -> bool) {
+        for primitives in &mut self.primitives {
+            primitives.retain(|primitive| keep(primitive.node()));
+        }
+    }
+
+    /// The primitive of the kind with the index `kind` that has the id
+    fn primitive(&self, kind: usize, id: usize) -> Option<&dyn DynPrimitive> {
+        self.primitives
+            .get(kind)?
+            .iter()
+            .find(|primitive| primitive.node().id == id)
+            .map(|primitive| primitive.as_ref())
+    }
+
+    fn primitive_node_mut(&mut self, kind: usize, id: usize) -> Option<&mut Node> {
+        self.primitives
+            .get_mut(kind)?
+            .iter_mut()
+            .find(|primitive| primitive.node().id == id)
+            .map(|primitive| primitive.node_mut())
+    }
+
+    /// Every primitive with the id of its node
+    fn all_primitives(&self) -> impl Iterator<Item = (NodeId, &dyn DynPrimitive)> {
+        self.primitives
+            .iter()
+            .enumerate()
+            .flat_map(|(kind, primitives)| {
+                primitives.iter().map(move |primitive| {
+                    (
+                        NodeId::Primitive(kind, primitive.node().id),
+                        primitive.as_ref(),
+                    )
+                })
+            })
+    }
+
+    /// The parent of `node` and where it is relative to it
+    fn node_transform(&self, node: NodeId) -> Option<(Option<usize>, Transform)> {
+        match node {
+            NodeId::Group(id) => self
+                .groups
+                .iter()
+                .find(|group| group.id == id)
+                .map(|group| (group.parent, group.local_transform())),
+            NodeId::Primitive(kind, id) => self
+                .primitive(kind, id)
+                .map(|primitive| (primitive.node().parent, primitive.node().local_transform())),
+        }
+    }
+
+    fn set_node_transform(&mut self, node: NodeId, Transform { position, rotation }: Transform) {
+        match node {
+            NodeId::Group(id) => {
+                if let Some(group) = self.groups.iter_mut().find(|group| group.id == id) {
+                    group.position = position;
+                    group.rotation = rotation;
+                }
+            }
+            NodeId::Primitive(kind, id) => {
+                if let Some(node) = self.primitive_node_mut(kind, id) {
+                    node.position = position;
+                    node.rotation = rotation;
+                }
+            }
+        }
+    }
+
+    fn set_node_parent(&mut self, node: NodeId, parent: Option<usize>) {
+        match node {
+            NodeId::Group(id) => {
+                if let Some(group) = self.groups.iter_mut().find(|group| group.id == id) {
+                    group.parent = parent;
+                }
+            }
+            NodeId::Primitive(kind, id) => {
+                if let Some(node) = self.primitive_node_mut(kind, id) {
+                    node.parent = parent;
+                }
+            }
+        }
+    }
+
+    /// Whether `node` or any group it is inside of is hidden
+    fn is_node_hidden(&self, node: NodeId) -> bool {
+        match node {
+            NodeId::Group(id) => any_group(&self.groups, Some(id), |group| group.hidden),
+            NodeId::Primitive(kind, id) => self.primitive(kind, id).is_some_and(|primitive| {
+                primitive.node().hidden
+                    || any_group(&self.groups, primitive.node().parent, |group| group.hidden)
+            }),
+        }
+    }
+
+    /// Whether `node` or any group it is inside of is locked
+    fn is_node_locked(&self, node: NodeId) -> bool {
+        match node {
+            NodeId::Group(id) => any_group(&self.groups, Some(id), |group| group.locked),
+            NodeId::Primitive(kind, id) => self.primitive(kind, id).is_some_and(|primitive| {
+                primitive.node().locked
+                    || any_group(&self.groups, primitive.node().parent, |group| group.locked)
+            }),
+        }
+    }
+
+    /// Whether the name of `node` or of anything inside of it contains the outliner filter
+    fn matches_outliner_filter(&self, node: NodeId) -> bool {
+        let filter = self.outliner_filter.to_lowercase();
+        let matches = |name: &str| name.to_lowercase().contains(&filter);
+        match node {
+            NodeId::Group(id) => {
+                self.groups.iter().any(|group| {
+                    is_inside(&self.groups, Some(group.id), id) && matches(&group.name)
+                }) || self.all_primitives().any(|(_, primitive)| {
+                    is_inside(&self.groups, primitive.node().parent, id)
+                        && matches(&primitive.node().name)
+                })
+            }
+            NodeId::Primitive(kind, id) => self
+                .primitive(kind, id)
+                .is_some_and(|primitive| matches(&primitive.node().name)),
+        }
+    }
+
+    /// The selected nodes that are not inside of a selected group, so moving the selection moves everything once
+    fn selection_roots(&self) -> Vec<NodeId> {
+        self.selection
+            .iter()
+            .copied()
+            .filter(|&node| {
+                let parent = self.node_transform(node).and_then(|(parent, _)| parent);
+                !self.selection.iter().any(|&other| {
+                    matches!(other, NodeId::Group(group) if is_inside(&self.groups, parent, group))
+                })
+            })
+            .collect()
+    }
+
+    fn select(&mut self, node: NodeId, modifiers: egui::Modifiers) {
+        if modifiers.shift {
+            // everything in the outliner between the last clicked node and this one
+            let range = self
+                .selection_anchor
+                .and_then(|anchor| {
+                    self.outliner_order
+                        .iter()
+                        .position(|&other| other == anchor)
+                })
+                .zip(self.outliner_order.iter().position(|&other| other == node))
+                .map(|(anchor, index)| {
+                    self.outliner_order[anchor.min(index)..=anchor.max(index)].to_vec()
+                })
+                .unwrap_or_else(|| vec![node]);
+            for node in range {
+                if !self.selection.contains(&node) {
+                    self.selection.push(node);
+                }
+            }
+        } else if modifiers.command {
+            if let Some(index) = self.selection.iter().position(|&other| other == node) {
+                self.selection.remove(index);
+            } else {
+                self.selection.push(node);
+            }
+            self.selection_anchor = Some(node);
+        } else {
+            self.selection = vec![node];
+            self.selection_anchor = Some(node);
+        }
+    }
+
+    /// `nodes` with everything inside of them, with the nodes themselves moved to the top level
+    /// and placed relative to `origin` in world space, so inserting them at the same origin puts them back where they are
+    fn extract_nodes(&self, nodes: &[NodeId], origin: Transform) -> SceneFile {
+        let mut file = SceneFile::empty(&self.primitive_kinds);
+        for &node in nodes {
+            let Some((parent, local_transform)) = self.node_transform(node) else {
+                continue;
+            };
+            let Transform { position, rotation } = origin
+                .inverse()
+                .then(group_transform(&self.groups, parent).then(local_transform));
+            match node {
+                NodeId::Group(root) => {
+                    for group in &self.groups {
+                        if is_inside(&self.groups, Some(group.id), root) {
+                            let mut group = group.clone();
+                            if group.id == root {
+                                (group.parent, group.position, group.rotation) =
+                                    (None, position, rotation);
+                            }
+                            file.groups.push(group);
+                        }
+                    }
+                    for (primitives, file_primitives) in
+                        self.primitives.iter().zip(&mut file.primitives)
+                    {
+                        file_primitives.extend(
+                            primitives
+                                .iter()
+                                .filter(|primitive| {
+                                    is_inside(&self.groups, primitive.node().parent, root)
+                                })
+                                .cloned(),
+                        );
+                    }
+                }
+                NodeId::Primitive(kind, id) => {
+                    if let Some(primitive) = self.primitive(kind, id) {
+                        let mut primitive = primitive.clone_box();
+                        let node = primitive.node_mut();
+                        (node.parent, node.position, node.rotation) = (None, position, rotation);
+                        file.primitives[kind].push(primitive);
+                    }
+                }
+            }
+        }
+        file
+    }
+
+    /// Adds everything in `file` inside of `parent` with fresh ids, with the top level of the file and its
+    /// lights placed relative to `origin` in world space. Returns what ended up directly inside of `parent`
+    fn insert_nodes(
+        &mut self,
+        file: SceneFile,
+        parent: Option<usize>,
+        origin: Transform,
+    ) -> Vec<NodeId> {
+        let SceneFile {
+            groups,
+            primitives,
+            lights,
+        } = file;
+        let parent_transform = group_transform(&self.groups, parent).inverse().then(origin);
+        let mut new_ids = std::collections::HashMap::new();
+        for group in &groups {
+            new_ids.insert(group.id, self.group_next_id);
+            self.group_next_id += 1;
+        }
+        // parents that are not in the file count as the top level of it
+        let mut inserted = vec![];
+        let place = |old_parent: Option<usize>, transform: Transform| match old_parent
+            .and_then(|old_parent| new_ids.get(&old_parent))
+        {
+            Some(&new_parent) => (Some(new_parent), transform, false),
+            None => (parent, parent_transform.then(transform), true),
+        };
+        for mut group in groups {
+            let (parent, transform, top_level) = place(group.parent, group.local_transform());
+            group.id = new_ids[&group.id];
+            (group.parent, group.position, group.rotation) =
+                (parent, transform.position, transform.rotation);
+            if top_level {
+                inserted.push(NodeId::Group(group.id));
+            }
+            self.groups.push(group);
+        }
+        for (kind, primitives) in primitives.into_iter().enumerate() {
+            for mut primitive in primitives {
+                let node = primitive.node_mut();
+                let (parent, transform, top_level) = place(node.parent, node.local_transform());
+                node.id = self.primitive_next_id;
+                self.primitive_next_id += 1;
+                (node.parent, node.position, node.rotation) =
+                    (parent, transform.position, transform.rotation);
+                if top_level {
+                    inserted.push(NodeId::Primitive(kind, node.id));
+                }
+                self.primitives[kind].push(primitive);
+            }
+        }
+        for mut light in lights {
+            light.id = self.light_next_id;
+            self.light_next_id += 1;
+            // lights are not in groups, so they are always in world space
+            light.position = origin.position + origin.rotation * light.position;
+            light.direction = origin.rotation * light.direction;
+            self.lights.push(light);
+        }
+        self.frame_count = 0;
+        inserted
+    }
+
+    /// Saves the selection as a prefab, placed around the middle of it
+    fn save_selection_as_prefab(&mut self, name: &str) -> anyhow::Result<()> {
+        let roots = self.selection_roots();
+        anyhow::ensure!(!roots.is_empty(), "nothing is selected");
+        let positions = roots
+            .iter()
+            .filter_map(|&node| {
+                let (parent, local_transform) = self.node_transform(node)?;
+                Some(
+                    group_transform(&self.groups, parent)
+                        .then(local_transform)
+                        .position,
+                )
+            })
+            .collect::<Vec<_>>();
+        let center = positions
+            .iter()
+            .fold(cgmath::vec4(0.0, 0.0, 0.0, 0.0), |sum, &position| {
+                sum + position
+            })
+            / positions.len() as f32;
+        let file = self.extract_nodes(
+            &roots,
+            Transform {
+                position: center,
+                rotation: cgmath::SquareMatrix::identity(),
+            },
+        );
+        self.prefab_library
+            .save(name, file, &self.primitive_kinds)?;
+        self.update_prefab_instances(name);
+        Ok(())
+    }
+
+    /// Adds an instance of the prefab in front of the camera and selects it
+    fn instantiate_prefab(&mut self, name: &str, linked: bool) {
+        let Some(file) = self
+            .prefab_library
+            .get(name)
+            .map(|prefab| prefab.file.clone())
+        else {
+            return;
+        };
+        let origin = Transform {
+            position: self.camera.position + cgmath::vec4(5.0, 0.0, 0.0, 0.0),
+            rotation: cgmath::SquareMatrix::identity(),
+        };
+        self.selection = if linked {
+            // the contents of a linked instance live in a group of their own, so they can be replaced
+            let id = self.group_next_id;
+            self.group_next_id += 1;
+            self.groups.push(Group {
+                name: name.into(),
+                id,
+                parent: None,
+                position: origin.position,
+                rotation: origin.rotation,
+                hidden: false,
+                locked: false,
+                prefab: Some(name.into()),
+            });
+            self.insert_nodes(file, Some(id), origin);
+            vec![NodeId::Group(id)]
+        } else {
+            self.insert_nodes(file, None, origin)
+        };
+        self.reveal_selected_node = true;
+        self.frame_count = 0;
+    }
+
+    /// Replaces what is inside of every linked instance of the prefab with what is in the prefab now.
+    /// The lights of the prefab were added once with the instance, they cannot be inside of it to be replaced
+    fn update_prefab_instances(&mut self, name: &str) {
+        let Some(file) = self.prefab_library.get(name).map(|prefab| SceneFile {
+            lights: vec![],
+            ..prefab.file.clone()
+        }) else {
+            return;
+        };
+        let instance_ids = self
+            .groups
+            .iter()
+            .filter(|group| group.prefab.as_deref() == Some(name))
+            .map(|group| group.id)
+            .collect::<Vec<_>>();
+        for id in instance_ids {
+            // an instance nested inside of another one is gone once the outer one got replaced
+            if !self.groups.iter().any(|group| group.id == id) {
+                continue;
+            }
+            let groups = self.groups.clone();
+            self.groups
+                .retain(|group| group.id == id || !is_inside(&groups, Some(group.id), id));
+            self.retain_primitives(|node| !is_inside(&groups, node.parent, id));
+            let origin = group_transform(&self.groups, Some(id));
+            self.insert_nodes(file.clone(), Some(id), origin);
+        }
+        self.retain_existing_selection();
+        self.frame_count = 0;
+    }
+
+    /// Saves what is inside of a linked instance as its prefab, which updates all the other instances
+    fn apply_instance_to_prefab(&mut self, id: usize) -> anyhow::Result<()> {
+        let Some(name) = self
+            .groups
+            .iter()
+            .find(|group| group.id == id)
+            .and_then(|group| group.prefab.clone())
+        else {
+            return Ok(());
+        };
+        let children = self
+            .groups
+            .iter()
+            .filter(|group| group.parent == Some(id))
+            .map(|group| NodeId::Group(group.id))
+            .chain(
+                self.all_primitives()
+                    .filter(|(_, primitive)| primitive.node().parent == Some(id))
+                    .map(|(node, _)| node),
+            )
+            .collect::<Vec<_>>();
+        let file = self.extract_nodes(&children, group_transform(&self.groups, Some(id)));
+        self.prefab_library
+            .save(&name, file, &self.primitive_kinds)?;
+        self.update_prefab_instances(&name);
+        Ok(())
+    }
+
+    /// Copies `nodes` with everything inside of them next to the originals, returning the copies
+    fn duplicate(&mut self, nodes: &[NodeId]) -> Vec<NodeId> {
+        let mut copies = vec![];
+        for &node in nodes {
+            let Some((parent, _)) = self.node_transform(node) else {
+                continue;
+            };
+            let mut file = self.extract_nodes(&[node], Transform::identity());
+            for group in file
+                .groups
+                .iter_mut()
+                .filter(|group| group.parent.is_none())
+            {
+                group.name += " Copy";
+            }
+            for node in file
+                .primitives
+                .iter_mut()
+                .flatten()
+                .map(|primitive| primitive.node_mut())
+                .filter(|node| node.parent.is_none())
+            {
+                node.name += " Copy";
+            }
+            copies.extend(self.insert_nodes(file, parent, Transform::identity()));
+        }
+        copies
+    }
+
+    /// The groups and primitives inside of `parent`, with everything inside of them
+    fn scene_tree_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        parent: Option<usize>,
+        actions: &mut Vec<SceneTreeAction>,
+    ) {
+        let filtering = !self.outliner_filter.is_empty();
+        let active_parent = self
+            .selection
+            .last()
+            .and_then(|&node| self.node_transform(node))
+            .and_then(|(parent, _)| parent);
+        let child_group_ids = self
+            .groups
+            .iter()
+            .filter(|group| group.parent == parent)
+            .map(|group| group.id)
+            .collect::<Vec<_>>();
+        for id in child_group_ids {
+            let node = NodeId::Group(id);
+            if filtering && !self.matches_outliner_filter(node) {
+                continue;
+            }
+            self.outliner_order.push(node);
+            let mut state = egui::collapsing_header::CollapsingState::load_with_default_open(
+                ui.ctx(),
+                ui.make_persistent_id(node),
+                false,
+            );
+            // open everything on the way to what got selected in the viewport, or that matches the filter
+            if filtering
+                || (self.reveal_selected_node && is_inside(&self.groups, active_parent, id))
+            {
+                state.set_open(true);
+            }
+            let selected = self.selection.contains(&node);
+            let (_, header, _) = state
+                .show_header(ui, |ui| {
+                    if let Some(group) = self.groups.iter_mut().find(|group| group.id == id) {
+                        if node_header_ui(
+                            ui,
+                            node,
+                            &group.name,
+                            &mut group.hidden,
+                            &mut group.locked,
+                            selected,
+                            actions,
+                        ) {
+                            self.frame_count = 0;
+                        }
+                    }
+                })
+                .body(|ui| {
+                    let locked = self.is_node_locked(node);
+                    if let Some(group) = self.groups.iter_mut().find(|group| group.id == id) {
+                        ui.add_enabled_ui(!locked, |ui| {
+                            if group_ui(ui, group) {
+                                self.frame_count = 0;
+                            }
+                            if let Some(prefab) = group.prefab.clone() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("Instance of {prefab}"));
+                                    if ui
+                                        .button("Apply to Prefab")
+                                        .on_hover_text("Save what is inside as the prefab, updating every instance of it")
+                                        .clicked()
+                                    {
+                                        actions.push(SceneTreeAction::ApplyToPrefab(id));
+                                    }
+                                    if ui.button("Unlink").clicked() {
+                                        group.prefab = None;
+                                    }
+                                });
+                            }
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(!locked, egui::Button::new("Delete"))
+                            .clicked()
+                        {
+                            actions.push(SceneTreeAction::Delete(node));
+                        }
+                        for (kind, PrimitiveKind { label, .. }) in
+                            self.primitive_kinds.iter().enumerate()
+                        {
+                            if ui.button(format!("New {label}")).clicked() {
+                                actions.push(SceneTreeAction::NewPrimitive(kind, Some(id)));
+                            }
+                        }
+                        if ui.button("New Group").clicked() {
+                            actions.push(SceneTreeAction::NewGroup(Some(id)));
+                        }
+                    });
+                    ui.separator();
+                    self.scene_tree_ui(ui, Some(id), actions);
+                });
+            if let Some(dropped) = header.response.dnd_release_payload::<NodeId>() {
+                actions.push(SceneTreeAction::Move(*dropped, Some(id)));
+            }
+        }
+
+        let child_primitives = self
+            .all_primitives()
+            .filter(|(_, primitive)| primitive.node().parent == parent)
+            .map(|(node, _)| node)
+            .collect::<Vec<_>>();
+        for node in child_primitives {
+            let NodeId::Primitive(kind, id) = node else {
+                continue;
+            };
+            if filtering && !self.matches_outliner_filter(node) {
+                continue;
+            }
+            self.outliner_order.push(node);
+            let mut state = egui::collapsing_header::CollapsingState::load_with_default_open(
+                ui.ctx(),
+                ui.make_persistent_id(node),
+                false,
+            );
+            let reveal = self.reveal_selected_node && self.selection.last() == Some(&node);
+            if reveal {
+                state.set_open(true);
+            }
+            let selected = self.selection.contains(&node);
+            let locked = self.is_node_locked(node);
+            let Some(primitive) = self.primitives[kind]
+                .iter_mut()
+                .find(|primitive| primitive.node().id == id)
+            else {
+                continue;
+            };
+            let (_, header, _) = state
+                .show_header(ui, |ui| {
+                    let node_properties = primitive.node_mut();
+                    if node_header_ui(
+                        ui,
+                        node,
+                        &node_properties.name,
+                        &mut node_properties.hidden,
+                        &mut node_properties.locked,
+                        selected,
+                        actions,
+                    ) {
+                        self.frame_count = 0;
+                    }
+                })
+                .body(|ui| {
+                    ui.add_enabled_ui(!locked, |ui| {
+                        if primitive_ui(ui, primitive.as_mut()) {
+                            self.frame_count = 0;
+                        }
+                        if ui.button("Delete").clicked() {
+                            actions.push(SceneTreeAction::Delete(node));
+                        }
+                    });
+                });
+            if reveal {
+                header.response.scroll_to_me(Some(egui::Align::Center));
+            }
+        }
+    }
+
+    /// Edits the properties the selected primitives of the same kind share all at once
+    fn selection_ui(&mut self, ui: &mut egui::Ui) {
+        let mut shown = false;
+        for (kind, PrimitiveKind { label, .. }) in self.primitive_kinds.iter().enumerate() {
+            let selected_ids = self
+                .selection
+                .iter()
+                .filter(|&&node| !self.is_node_locked(node))
+                .filter_map(|&node| match node {
+                    NodeId::Primitive(node_kind, id) if node_kind == kind => Some(id),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if selected_ids.len() < 2 {
+                continue;
+            }
+            let Some(first) = self
+                .primitive(kind, selected_ids[0])
+                .map(|primitive| primitive.clone_box())
+            else {
+                continue;
+            };
+            egui::CollapsingHeader::new(format!("{} Selected {label}s", selected_ids.len()))
+                .default_open(true)
+                .show(ui, |ui| {
+                    // shows the values of the first one, and whatever gets changed is set on all of them
+                    let mut edited = first.clone();
+                    edited.properties_ui(ui);
+                    if !edited.eq_dyn(first.as_ref()) {
+                        for primitive in &mut self.primitives[kind] {
+                            if selected_ids.contains(&primitive.node().id) {
+                                primitive.copy_changed_properties(first.as_ref(), edited.as_ref());
+                            }
+                        }
+                        self.frame_count = 0;
+                    }
+                });
+            shown = true;
+        }
+        if shown {
+            ui.separator();
+        }
+    }
+
+    fn apply_scene_tree_action(&mut self, action: SceneTreeAction) {
+        match action {
+            SceneTreeAction::Select(node, modifiers) => {
+                self.select(node, modifiers);
+                return;
+            }
+            SceneTreeAction::Delete(node) => {
+                if let NodeId::Group(id) = node {
+                    // along with everything inside of it
+                    let groups = self.groups.clone();
+                    self.groups
+                        .retain(|group| !is_inside(&groups, Some(group.id), id));
+                    self.retain_primitives(|node| !is_inside(&groups, node.parent, id));
+                }
+                if let NodeId::Primitive(kind, id) = node {
+                    self.primitives[kind].retain(|primitive| primitive.node().id != id);
+                }
+                let selection = std::mem::take(&mut self.selection);
+                self.selection = selection
+                    .into_iter()
+                    .filter(|&node| self.node_transform(node).is_some())
+                    .collect();
+            }
+            SceneTreeAction::DeleteSelection => {
+                for node in self.selection_roots() {
+                    if !self.is_node_locked(node) {
+                        self.apply_scene_tree_action(SceneTreeAction::Delete(node));
+                    }
+                }
+            }
+            SceneTreeAction::DuplicateSelection => {
+                self.selection = self.duplicate(&self.selection_roots());
+            }
+            SceneTreeAction::Move(node, parent) => {
+                // a group cannot go inside of itself
+                if matches!(node, NodeId::Group(id) if is_inside(&self.groups, parent, id)) {
+                    return;
+                }
+                let Some((old_parent, local_transform)) = self.node_transform(node) else {
+                    return;
+                };
+                // so it stays where it is in the world
+                let transform = group_transform(&self.groups, parent)
+                    .inverse()
+                    .then(group_transform(&self.groups, old_parent).then(local_transform));
+                self.set_node_parent(node, parent);
+                self.set_node_transform(node, transform);
+            }
+            SceneTreeAction::NewPrimitive(kind, parent) => {
+                let primitive_kind = self.primitive_kinds[kind];
+                self.primitives[kind].push(primitive_kind.new_primitive(Node {
+                    name: format!("New {}", primitive_kind.label),
+                    id: self.primitive_next_id,
+                    parent,
+                    position: cgmath::vec4(2.0, 0.0, 0.0, 0.0),
+                    rotation: cgmath::SquareMatrix::identity(),
+                    hidden: false,
+                    locked: false,
+                }));
+                self.primitive_next_id += 1;
+            }
+            SceneTreeAction::NewGroup(parent) => {
+                self.groups.push(Group {
+                    name: "New Group".into(),
+                    id: self.group_next_id,
+                    parent,
+                    position: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+                    rotation: cgmath::SquareMatrix::identity(),
+                    hidden: false,
+                    locked: false,
+                    prefab: None,
+                });
+                self.group_next_id += 1;
+            }
+            SceneTreeAction::ApplyToPrefab(id) => {
+                self.prefab_status = self
+                    .apply_instance_to_prefab(id)
+                    .err()
+                    .map(|error| format!("Error: {error}"));
+            }
+        }
+        self.frame_count = 0;
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+        let frame_count_before_ui = self.frame_count;
+
+        // text fields have their own undo
+        if !ctx.wants_keyboard_input() {
+            let (undo, redo) = ctx.input_mut(|input| {
+                let redo = input.consume_shortcut(&egui::KeyboardShortcut::new(
+                    egui::Modifiers::COMMAND | egui::Modifiers::SHIFT,
+                    egui::Key::Z,
+                ));
+                let undo = input.consume_shortcut(&egui::KeyboardShortcut::new(
+                    egui::Modifiers::COMMAND,
+                    egui::Key::Z,
+                ));
+                (undo, redo)
+            });
+            if undo {
+                let snapshot = self.history.undo().cloned();
+                self.restore(snapshot);
+            } else if redo {
+                let snapshot = self.history.redo().cloned();
+                self.restore(snapshot);
+            }
+
+            // the system clipboard goes through the copy and paste events, so this works between instances
+            let (copy, pasted, duplicate) = ctx.input_mut(|input| {
+                let copy = input
+                    .events
+                    .iter()
+                    .any(|event| matches!(event, egui::Event::Copy));
+                let pasted = input.events.iter().find_map(|event| match event {
+                    egui::Event::Paste(text) => Some(text.clone()),
+                    _ => None,
+                });
+                let duplicate = input.consume_shortcut(&egui::KeyboardShortcut::new(
+                    egui::Modifiers::COMMAND,
+                    egui::Key::D,
+                ));
+                (copy, pasted, duplicate)
+            });
+            if copy && !self.selection.is_empty() {
+                ctx.copy_text(
+                    self.extract_nodes(&self.selection_roots(), Transform::identity())
+                        .to_text(&self.primitive_kinds),
+                );
+            }
+            // anything on the clipboard that is not a scene is not meant for this
+            if let Some(file) =
+                pasted.and_then(|text| SceneFile::from_text(&text, &self.primitive_kinds).ok())
+            {
+                self.selection = self.insert_nodes(file, None, Transform::identity());
+                self.reveal_selected_node = true;
+            }
+            if duplicate {
+                self.selection = self.duplicate(&self.selection_roots());
+            }
+        }
+
+        egui::Window::new("Camera").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Position:");
+                if vec4_ui(ui, &mut self.camera.position) {
+                    self.frame_count = 0;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Fov:");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut self.camera.fov)
+                            .speed(0.1)
+                            .range(1.0..=179.0),
+                    )
+                    .changed()
+                {
+                    self.frame_count = 0;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Up Sky Color:");
+                if ui
+                    .color_edit_button_rgb(self.camera.up_sky_color.as_mut())
+                    .changed()
                 {
                     self.frame_count = 0;
                 }
@@ -353,6 +2407,25 @@ impl eframe::App for App {
                     self.frame_count = 0;
                 };
                 self.camera.bounce_count = self.camera.bounce_count.max(1);
+                // a depth past the last bounce would keep russian roulette from ever running
+                self.camera.russian_roulette_depth = self
+                    .camera
+                    .russian_roulette_depth
+                    .clamp(1, self.camera.bounce_count);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Russian Roulette Depth:");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut self.camera.russian_roulette_depth)
+                            .speed(1)
+                            .range(1..=self.camera.bounce_count),
+                    )
+                    .on_hover_text("Paths may be ended early once they have bounced this many times, up to the bounce count")
+                    .changed()
+                {
+                    self.frame_count = 0;
+                };
             });
             ui.horizontal(|ui| {
                 ui.label("Sample Count:");
@@ -364,47 +2437,734 @@ impl eframe::App for App {
                 };
                 self.camera.sample_count = self.camera.sample_count.max(1);
             });
+            ui.horizontal(|ui| {
+                ui.label("Sampler:");
+                let old_sampler = self.camera.sampler;
+                egui::ComboBox::from_id_source("Sampler")
+                    .selected_text(self.camera.sampler.name())
+                    .show_ui(ui, |ui| {
+                        for sampler in Sampler::ALL {
+                            ui.selectable_value(&mut self.camera.sampler, sampler, sampler.name());
+                        }
+                    });
+                if self.camera.sampler != old_sampler {
+                    self.frame_count = 0;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Seed:");
+                if ui
+                    .add(egui::DragValue::new(&mut self.camera.seed).speed(1))
+                    .changed()
+                {
+                    self.frame_count = 0;
+                }
+                if ui.button("Randomize").clicked() {
+                    self.camera.seed = rand::random();
+                    self.frame_count = 0;
+                }
+            });
+            egui::CollapsingHeader::new("Preview").show(ui, |ui| {
+                let preview = &mut self.camera.preview;
+                ui.checkbox(&mut preview.enabled, "Preview While Editing")
+                    .on_hover_text("Show cheap direct lighting until editing stops");
+                ui.add_enabled_ui(preview.enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Delay:");
+                        ui.add(
+                            egui::DragValue::new(&mut preview.delay)
+                                .speed(0.01)
+                                .range(0.0..=f32::INFINITY)
+                                .suffix("s"),
+                        );
+                    });
+                    if ui
+                        .checkbox(&mut preview.ambient_occlusion, "Ambient Occlusion")
+                        .changed()
+                    {
+                        self.frame_count = 0;
+                    }
+                    ui.add_enabled_ui(preview.ambient_occlusion, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Ambient Occlusion Distance:");
+                            if ui
+                                .add(
+                                    egui::DragValue::new(
+                                        &mut preview.ambient_occlusion_distance,
+                                    )
+                                    .speed(0.01)
+                                    .range(0.0..=f32::INFINITY),
+                                )
+                                .changed()
+                            {
+                                self.frame_count = 0;
+                            }
+                        });
+                    });
+                });
+            });
+            egui::CollapsingHeader::new("Adaptive Sampling").show(ui, |ui| {
+                // every pixel keeps track of its own variance, so this applies without restarting
+                let adaptive_sampling = &mut self.camera.adaptive_sampling;
+                ui.checkbox(&mut adaptive_sampling.enabled, "Enabled")
+                    .on_hover_text("Stop sampling pixels that have converged");
+                ui.add_enabled_ui(adaptive_sampling.enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Error Threshold:");
+                        ui.add(
+                            egui::DragValue::new(&mut adaptive_sampling.threshold)
+                                .speed(0.001)
+                                .range(0.0..=f32::INFINITY),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Min Sample Count:");
+                        ui.add(
+                            egui::DragValue::new(&mut adaptive_sampling.min_sample_count)
+                                .speed(1)
+                                .range(1..=u32::MAX),
+                        );
+                    });
+                });
+            });
+            egui::CollapsingHeader::new("Render Target").show(ui, |ui| {
+                let target = &mut self.render_target;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut target.limit_sample_count, "Max Samples:");
+                    ui.add_enabled(
+                        target.limit_sample_count,
+                        egui::DragValue::new(&mut target.max_sample_count)
+                            .speed(1)
+                            .range(1..=u32::MAX),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut target.limit_time, "Max Time:");
+                    ui.add_enabled(
+                        target.limit_time,
+                        egui::DragValue::new(&mut target.max_time)
+                            .speed(0.1)
+                            .range(0.0..=f32::INFINITY)
+                            .suffix("s"),
+                    );
+                });
+            });
             ui.allocate_space(ui.available_size());
         });
 
-        egui::Window::new("Hyper Spheres")
+        egui::Window::new("Tiled Render").show(ctx, |ui| {
+            let settings = &mut self.tiled_render_settings;
+            ui.add_enabled_ui(self.tiled_render.is_none(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Size:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.width)
+                            .speed(1)
+                            .range(1..=u32::MAX),
+                    );
+                    ui.label("x");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.height)
+                            .speed(1)
+                            .range(1..=u32::MAX),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Tile Size:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.tile_size)
+                            .speed(1)
+                            .range(16..=4096),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Samples Per Pixel:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.samples_per_pixel)
+                            .speed(1)
+                            .range(1..=u32::MAX),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Path:");
+                    ui.text_edit_singleline(&mut settings.path);
+                });
+                // it filters across the whole image, so every tile would get seams at its edges
+                ui.weak("The denoiser is only used in the viewport");
+                // the glow reaches across the edges of the strips just the same
+                ui.weak("Bloom is only used in the viewport");
+            });
+            if let Some(progress) = self.tiled_render.as_ref().map(TiledRender::progress) {
+                ui.horizontal(|ui| {
+                    ui.add(egui::ProgressBar::new(progress).show_percentage());
+                    if ui.button("Cancel").clicked() {
+                        self.tiled_render_status =
+                            Some(match self.tiled_render.take().unwrap().cancel() {
+                                Ok(()) => "Cancelled".into(),
+                                Err(error) => format!("Cancelled, but not deleted: {error}"),
+                            });
+                    }
+                });
+            } else if ui.button("Render").clicked() {
+                let egui_wgpu::RenderState { device, queue, .. } =
+                    frame.wgpu_render_state().unwrap();
+                // the file gets the image itself, without the view mode or selection of the viewport
+                let view_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Tiled Render View Uniform Buffer"),
+                    size: GpuView::SHADER_SIZE.get(),
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+                    mapped_at_creation: false,
+                });
+                let mut buffer = UniformBuffer::new([0; GpuView::SHADER_SIZE.get() as _]);
+                buffer
+                    .write(&GpuView {
+                        mode: ViewMode::Beauty.gpu_mode(),
+                        ..self.view.gpu_view()
+                    })
+                    .unwrap();
+                queue.write_buffer(&view_uniform_buffer, 0, &buffer.into_inner());
+                let no_selected_objects_storage_buffer =
+                    device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("Tiled Render Selected Objects Storage Buffer"),
+                        size: u32::SHADER_SIZE.get(),
+                        usage: wgpu::BufferUsages::STORAGE,
+                        mapped_at_creation: false,
+                    });
+                let view_bind_group = create_view_bind_group(
+                    device,
+                    &self.view_bind_group_layout,
+                    &view_uniform_buffer,
+                    &no_selected_objects_storage_buffer,
+                );
+                let tiled_render = TiledRender::new(
+                    device,
+                    &self.main_texture_bind_group_layout,
+                    &self.display_texture_bind_group_layout,
+                    &self.texture_bind_group_layout,
+                    view_bind_group,
+                    settings,
+                    self.camera.sample_count,
+                );
+                match tiled_render {
+                    Ok(tiled_render) => {
+                        self.tiled_render = Some(tiled_render);
+                        self.tiled_render_status = None;
+                    }
+                    Err(error) => self.tiled_render_status = Some(format!("Error: {error}")),
+                }
+            }
+            if let Some(status) = &self.tiled_render_status {
+                ui.label(status);
+            }
+        });
+
+        egui::Window::new("Resolution").show(ctx, |ui| {
+            let resolution = &mut self.resolution;
+            ui.horizontal(|ui| {
+                ui.label("Mode:");
+                egui::ComboBox::from_id_source("Resolution Mode")
+                    .selected_text(resolution.mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in ResolutionMode::ALL {
+                            ui.selectable_value(&mut resolution.mode, mode, mode.name());
+                        }
+                    });
+            });
+            // a new size restarts the accumulation on its own when the textures get resized
+            match resolution.mode {
+                ResolutionMode::Scaled => {
+                    ui.horizontal(|ui| {
+                        ui.label("Scale:");
+                        ui.add(
+                            egui::Slider::new(&mut resolution.scale, 0.25..=2.0)
+                                .custom_formatter(|scale, _| format!("{:.0}%", scale * 100.0))
+                                .custom_parser(|text| {
+                                    text.trim_end_matches('%')
+                                        .parse::<f64>()
+                                        .ok()
+                                        .map(|percent| percent / 100.0)
+                                }),
+                        );
+                    });
+                }
+                ResolutionMode::Fixed => {
+                    ui.horizontal(|ui| {
+                        ui.label("Size:");
+                        ui.add(
+                            egui::DragValue::new(&mut resolution.width)
+                                .speed(1)
+                                .range(1..=u32::MAX),
+                        );
+                        ui.label("x");
+                        ui.add(
+                            egui::DragValue::new(&mut resolution.height)
+                                .speed(1)
+                                .range(1..=u32::MAX),
+                        );
+                    });
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label("Filter:");
+                ui.selectable_value(&mut resolution.filter, wgpu::FilterMode::Nearest, "Nearest");
+                ui.selectable_value(&mut resolution.filter, wgpu::FilterMode::Linear, "Bilinear");
+            });
+        });
+
+        egui::Window::new("View").show(ctx, |ui| {
+            let view = &mut self.view;
+            ui.horizontal(|ui| {
+                ui.label("Mode:");
+                egui::ComboBox::from_id_source("View Mode")
+                    .selected_text(view.mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in ViewMode::ALL {
+                            ui.selectable_value(&mut view.mode, mode, mode.name());
+                        }
+                    });
+            });
+            ui.checkbox(&mut view.pixel_inspector, "Pixel Inspector")
+                .on_hover_text("Show what is under the cursor");
+            ui.horizontal(|ui| {
+                ui.label("Gizmo:");
+                egui::ComboBox::from_id_source("Gizmo Mode")
+                    .selected_text(self.gizmo.mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in GizmoMode::ALL {
+                            ui.selectable_value(&mut self.gizmo.mode, mode, mode.name());
+                        }
+                    });
+            });
+            match view.mode {
+                ViewMode::Beauty | ViewMode::Albedo | ViewMode::ObjectId => {}
+                ViewMode::Depth => {
+                    ui.horizontal(|ui| {
+                        ui.label("Max Depth:");
+                        ui.add(
+                            egui::DragValue::new(&mut view.max_depth)
+                                .speed(0.1)
+                                .range(0.001..=f32::INFINITY),
+                        );
+                    });
+                }
+                ViewMode::Normal => {
+                    ui.horizontal(|ui| {
+                        ui.label("Axes:");
+                        for (channel, axis) in ["Red", "Green", "Blue"]
+                            .into_iter()
+                            .zip(&mut view.normal_axes)
+                        {
+                            egui::ComboBox::from_id_source(("Normal Axis", channel))
+                                .width(40.0)
+                                .selected_text(["x", "y", "z", "w"][*axis])
+                                .show_ui(ui, |ui| {
+                                    for (i, name) in ["x", "y", "z", "w"].into_iter().enumerate() {
+                                        ui.selectable_value(axis, i, name);
+                                    }
+                                })
+                                .response
+                                .on_hover_text(channel);
+                        }
+                    });
+                }
+                ViewMode::WCoordinate => {
+                    ui.horizontal(|ui| {
+                        ui.label("W Range:");
+                        ui.add(
+                            egui::DragValue::new(&mut view.w_range)
+                                .speed(0.1)
+                                .range(0.001..=f32::INFINITY),
+                        );
+                    });
+                }
+                ViewMode::BounceCount => {
+                    ui.horizontal(|ui| {
+                        ui.label("Max Bounce Count:");
+                        ui.add(
+                            egui::DragValue::new(&mut view.max_bounce_count)
+                                .speed(0.1)
+                                .range(1.0..=f32::INFINITY),
+                        );
+                    });
+                }
+                ViewMode::IntersectionTestCount => {
+                    ui.horizontal(|ui| {
+                        ui.label("Max Intersection Tests:");
+                        ui.add(
+                            egui::DragValue::new(&mut view.max_intersection_test_count)
+                                .speed(1.0)
+                                .range(1.0..=f32::INFINITY),
+                        );
+                    });
+                }
+                ViewMode::SampleCount => {
+                    ui.horizontal(|ui| {
+                        ui.label("Max Sample Count:");
+                        ui.add(
+                            egui::DragValue::new(&mut view.max_sample_count)
+                                .speed(1.0)
+                                .range(1.0..=f32::INFINITY),
+                        );
+                    });
+                }
+            }
+        });
+
+        egui::Window::new("Denoiser").show(ctx, |ui| {
+            let settings = &mut self.denoiser_settings;
+            let mut changed = false;
+            changed |= ui.checkbox(&mut settings.enabled, "Enabled").changed();
+            ui.add_enabled_ui(settings.enabled, |ui| {
+                changed |= ui
+                    .checkbox(&mut settings.temporal, "Temporal History")
+                    .on_hover_text("Reuse the previous frame while moving")
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("Iterations:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.iterations)
+                                .speed(0.1)
+                                .range(1..=denoiser::MAX_ITERATIONS),
+                        )
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Max History:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.max_history)
+                                .speed(0.1)
+                                .range(0.0..=f32::INFINITY),
+                        )
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Color Sigma:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.color_sigma)
+                                .speed(0.01)
+                                .range(0.0..=f32::INFINITY),
+                        )
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Normal Sigma:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.normal_sigma)
+                                .speed(0.1)
+                                .range(0.0..=f32::INFINITY),
+                        )
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Depth Sigma:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.depth_sigma)
+                                .speed(0.001)
+                                .range(0.0001..=f32::INFINITY),
+                        )
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Albedo Sigma:");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.albedo_sigma)
+                                .speed(0.001)
+                                .range(0.0001..=f32::INFINITY),
+                        )
+                        .changed();
+                });
+            });
+            // the history is only picked up when the accumulation restarts
+            if changed {
+                self.frame_count = 0;
+            }
+        });
+
+        egui::Window::new("Bloom").show(ctx, |ui| {
+            // bloom only changes what is shown, so none of this restarts the accumulation
+            let settings = &mut self.bloom_settings;
+            ui.checkbox(&mut settings.enabled, "Enabled")
+                .on_hover_text("Only in the viewport, tiled renders are saved without it");
+            ui.add_enabled_ui(settings.enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Intensity:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.intensity)
+                            .speed(0.01)
+                            .range(0.0..=f32::INFINITY),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Threshold:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.threshold)
+                            .speed(0.01)
+                            .range(0.0..=f32::INFINITY),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Knee:");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.knee)
+                            .speed(0.01)
+                            .range(0.0..=f32::INFINITY),
+                    )
+                    .on_hover_text("How softly the threshold fades in");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Radius:");
+                    ui.add(egui::Slider::new(&mut settings.radius, 0.0..=1.0));
+                });
+            });
+        });
+
+        egui::Window::new("Outliner")
+            .hscroll(false)
+            .vscroll(false)
+            .show(ctx, |ui| {
+                let mut actions = vec![];
+                ui.horizontal(|ui| {
+                    ui.label("Search:");
+                    ui.text_edit_singleline(&mut self.outliner_filter);
+                });
+                ui.horizontal(|ui| {
+                    for (kind, PrimitiveKind { label, .. }) in
+                        self.primitive_kinds.iter().enumerate()
+                    {
+                        if ui.button(format!("New {label}")).clicked() {
+                            actions.push(SceneTreeAction::NewPrimitive(kind, None));
+                        }
+                    }
+                    if ui.button("New Group").clicked() {
+                        actions.push(SceneTreeAction::NewGroup(None));
+                    }
+                    let has_selection = !self.selection.is_empty();
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Duplicate"))
+                        .clicked()
+                    {
+                        actions.push(SceneTreeAction::DuplicateSelection);
+                    }
+                    if ui
+                        .add_enabled(has_selection, egui::Button::new("Delete"))
+                        .clicked()
+                    {
+                        actions.push(SceneTreeAction::DeleteSelection);
+                    }
+                });
+                ui.separator();
+                self.selection_ui(ui);
+                egui::ScrollArea::vertical()
+                    .auto_shrink(false)
+                    .show(ui, |ui| {
+                        self.outliner_order.clear();
+                        self.scene_tree_ui(ui, None, &mut actions);
+                        self.reveal_selected_node = false;
+                        let (_, dropped) = ui.dnd_drop_zone::<NodeId, _>(
+                            egui::Frame::default().inner_margin(4.0),
+                            |ui| {
+                                ui.weak("Drop here to move to the top level");
+                            },
+                        );
+                        if let Some(node) = dropped {
+                            actions.push(SceneTreeAction::Move(*node, None));
+                        }
+                        for action in actions {
+                            self.apply_scene_tree_action(action);
+                        }
+                    });
+            });
+
+        if let Some(watcher) = &mut self.scene_watcher {
+            if watcher.changed() {
+                // a file that is only partly written fails to load, and gets loaded again once it is done
+                self.scene_status = Some(
+                    match SceneFile::load(watcher.path(), &self.primitive_kinds) {
+                        Ok(file) => {
+                            self.load_scene(file);
+                            format!("Reloaded {}", self.scene_path)
+                        }
+                        Err(error) => format!("Error: {error:#}"),
+                    },
+                );
+            }
+            ctx.request_repaint_after(file_watch::CHECK_INTERVAL);
+        }
+
+        egui::Window::new("Scene").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Path:");
+                ui.text_edit_singleline(&mut self.scene_path);
+            });
+            ui.horizontal(|ui| {
+                let mut watch = self.scene_watcher.is_some();
+                if ui.button("Open").clicked() {
+                    self.scene_status = Some(match self.open_scene(watch) {
+                        Ok(()) => format!("Opened {}", self.scene_path),
+                        Err(error) => format!("Error: {error:#}"),
+                    });
+                }
+                if ui.button("Save").clicked() {
+                    self.scene_status = Some(match self.save_scene() {
+                        Ok(()) => format!("Saved to {}", self.scene_path),
+                        Err(error) => format!("Error: {error:#}"),
+                    });
+                }
+                if ui
+                    .checkbox(&mut watch, "Reload When Changed")
+                    .on_hover_text("Open the file again whenever something else changes it")
+                    .changed()
+                {
+                    self.scene_watcher = watch.then(|| FileWatcher::new(self.scene_path.as_str()));
+                }
+            });
+            if let Some(status) = &self.scene_status {
+                ui.label(status);
+            }
+        });
+
+        egui::Window::new("Prefabs").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Directory:");
+                ui.text_edit_singleline(&mut self.prefab_library.directory);
+                if ui.button("Reload").clicked() {
+                    self.prefab_status = match self.prefab_library.reload(&self.primitive_kinds) {
+                        Ok(()) => {
+                            // the files may have been edited outside of here
+                            let names = self
+                                .prefab_library
+                                .prefabs
+                                .iter()
+                                .map(|prefab| prefab.name.clone())
+                                .collect::<Vec<_>>();
+                            for name in names {
+                                self.update_prefab_instances(&name);
+                            }
+                            None
+                        }
+                        Err(error) => Some(format!("Error: {error}")),
+                    };
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut self.prefab_name);
+                if ui
+                    .add_enabled(
+                        !self.selection.is_empty() && !self.prefab_name.is_empty(),
+                        egui::Button::new("Save Selection"),
+                    )
+                    .clicked()
+                {
+                    let name = self.prefab_name.clone();
+                    self.prefab_status = match self.save_selection_as_prefab(&name) {
+                        Ok(()) => Some(format!("Saved {name}")),
+                        Err(error) => Some(format!("Error: {error}")),
+                    };
+                }
+            });
+            ui.checkbox(&mut self.prefab_linked, "Linked")
+                .on_hover_text("New instances follow the changes to their prefab");
+            ui.separator();
+            let mut instantiate = None;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for prefab in &self.prefab_library.prefabs {
+                    ui.horizontal(|ui| {
+                        if ui.button("Add").clicked() {
+                            instantiate = Some(prefab.name.clone());
+                        }
+                        ui.label(&prefab.name);
+                        ui.weak(format!("{} objects", prefab.file.node_count()));
+                    });
+                }
+            });
+            if let Some(name) = instantiate {
+                self.instantiate_prefab(&name, self.prefab_linked);
+            }
+            if let Some(status) = &self.prefab_status {
+                ui.label(status);
+            }
+        });
+
+        egui::Window::new("Lights")
             .hscroll(false)
             .vscroll(false)
             .show(ctx, |ui| {
                 egui::ScrollArea::vertical()
                     .auto_shrink(false)
                     .show(ui, |ui| {
-                        self.hyper_spheres.retain_mut(|hyper_sphere| {
+                        self.lights.retain_mut(|light| {
                             let mut delete = false;
-                            egui::CollapsingHeader::new(&hyper_sphere.name)
-                                .id_source(hyper_sphere.id)
+                            egui::CollapsingHeader::new(&light.name)
+                                .id_source(("Light", light.id))
                                 .show(ui, |ui| {
                                     ui.horizontal(|ui| {
                                         ui.label("Name:");
-                                        ui.text_edit_singleline(&mut hyper_sphere.name);
+                                        ui.text_edit_singleline(&mut light.name);
                                     });
                                     ui.horizontal(|ui| {
-                                        ui.label("Position:");
-                                        if vec4_ui(ui, &mut hyper_sphere.position) {
+                                        ui.label("Kind:");
+                                        let old_kind = light.kind;
+                                        egui::ComboBox::from_id_source(("Light Kind", light.id))
+                                            .selected_text(match light.kind {
+                                                LightKind::Point => "Point",
+                                                LightKind::Directional => "Directional",
+                                            })
+                                            .show_ui(ui, |ui| {
+                                                ui.selectable_value(
+                                                    &mut light.kind,
+                                                    LightKind::Point,
+                                                    "Point",
+                                                );
+                                                ui.selectable_value(
+                                                    &mut light.kind,
+                                                    LightKind::Directional,
+                                                    "Directional",
+                                                );
+                                            });
+                                        if light.kind != old_kind {
                                             self.frame_count = 0;
                                         }
                                     });
+                                    match light.kind {
+                                        LightKind::Point => {
+                                            ui.horizontal(|ui| {
+                                                ui.label("Position:");
+                                                if vec4_ui(ui, &mut light.position) {
+                                                    self.frame_count = 0;
+                                                }
+                                            });
+                                        }
+                                        LightKind::Directional => {
+                                            ui.horizontal(|ui| {
+                                                ui.label("Direction:");
+                                                if vec4_ui(ui, &mut light.direction) {
+                                                    self.frame_count = 0;
+                                                }
+                                            });
+                                        }
+                                    }
                                     ui.horizontal(|ui| {
-                                        ui.label("Radius:");
-                                        if ui
-                                            .add(
-                                                egui::DragValue::new(&mut hyper_sphere.radius)
-                                                    .speed(0.1),
-                                            )
-                                            .changed()
+                                        ui.label("Color:");
+                                        if ui.color_edit_button_rgb(light.color.as_mut()).changed()
                                         {
                                             self.frame_count = 0;
                                         }
                                     });
                                     ui.horizontal(|ui| {
-                                        ui.label("Color:");
+                                        ui.label("Intensity:");
                                         if ui
-                                            .color_edit_button_rgb(hyper_sphere.color.as_mut())
+                                            .add(
+                                                egui::DragValue::new(&mut light.intensity)
+                                                    .speed(0.1)
+                                                    .range(0.0..=f32::INFINITY),
+                                            )
                                             .changed()
                                         {
                                             self.frame_count = 0;
@@ -417,20 +3177,191 @@ impl eframe::App for App {
                                 });
                             !delete
                         });
-                        if ui.button("New Hyper Sphere").clicked() {
-                            self.hyper_spheres.push(HyperSphere {
-                                name: "New Hyper Sphere".into(),
-                                id: self.hyper_sphere_next_id,
-                                position: cgmath::vec4(2.0, 0.0, 0.0, 0.0),
-                                color: cgmath::vec3(0.9, 0.9, 0.9),
-                                radius: 1.0,
+                        if ui.button("New Light").clicked() {
+                            self.lights.push(Light {
+                                name: "New Light".into(),
+                                id: self.light_next_id,
+                                kind: LightKind::Point,
+                                position: cgmath::vec4(0.0, 2.0, 0.0, 0.0),
+                                direction: cgmath::vec4(0.0, -1.0, 0.0, 0.0),
+                                color: cgmath::vec3(1.0, 1.0, 1.0),
+                                intensity: 10.0,
                             });
-                            self.hyper_sphere_next_id += 1;
+                            self.light_next_id += 1;
                             self.frame_count = 0;
                         }
                     });
             });
 
+        let samples_per_pixel = self.frame_count * self.camera.sample_count;
+        let finished = !self.previewing
+            && self
+                .render_target
+                .is_reached(samples_per_pixel, self.render_time);
+        // only the features that are used get compiled in, so changing them needs another compile
+        let changed_shaders: &[Shader] = if self.shader_reload.changed() {
+            &Shader::ALL
+        } else if self.camera.shader_defines() != self.raytracing_defines {
+            &[Shader::Raytracing]
+        } else {
+            &[]
+        };
+        if !changed_shaders.is_empty() {
+            let device = &frame.wgpu_render_state().unwrap().device;
+            self.reload_shaders(device, changed_shaders);
+        }
+        if self.shader_reload.is_enabled() {
+            ctx.request_repaint_after(file_watch::CHECK_INTERVAL);
+        }
+
+        egui::Window::new("Shaders")
+            .default_open(false)
+            .show(ctx, |ui| {
+                let mut enabled = self.shader_reload.is_enabled();
+                ui.horizontal(|ui| {
+                    ui.label("Directory:");
+                    ui.add_enabled(
+                        !enabled,
+                        egui::TextEdit::singleline(&mut self.shader_reload.directory),
+                    );
+                });
+                let toggled = ui
+                    .checkbox(&mut enabled, "Load From Disk")
+                    .on_hover_text(
+                        "Compile the shaders in the directory again whenever they change",
+                    )
+                    .changed();
+                let reload = ui
+                    .add_enabled(enabled, egui::Button::new("Reload"))
+                    .clicked();
+                if toggled || reload {
+                    self.shader_reload.set_enabled(enabled);
+                    let device = &frame.wgpu_render_state().unwrap().device;
+                    self.reload_shaders(device, &Shader::ALL);
+                }
+                for (shader, error) in &self.shader_reload.errors {
+                    ui.separator();
+                    ui.label(format!(
+                        "{} failed to compile, using the last one that did:",
+                        shader.file_name()
+                    ));
+                    egui::ScrollArea::both()
+                        .id_source(shader.file_name())
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            ui.label(
+                                egui::RichText::new(error)
+                                    .monospace()
+                                    .color(ui.visuals().error_fg_color),
+                            );
+                        });
+                }
+            });
+
+        egui::Window::new("History").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                    .on_hover_text("Ctrl+Z")
+                    .clicked()
+                {
+                    let snapshot = self.history.undo().cloned();
+                    self.restore(snapshot);
+                }
+                if ui
+                    .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                    .on_hover_text("Ctrl+Shift+Z")
+                    .clicked()
+                {
+                    let snapshot = self.history.redo().cloned();
+                    self.restore(snapshot);
+                }
+            });
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let mut go_to = None;
+                for (index, label) in self.history.labels().enumerate() {
+                    let current = index == self.history.current();
+                    // the states that would be redone are greyed out
+                    let text = if index > self.history.current() {
+                        egui::RichText::new(label).weak()
+                    } else {
+                        egui::RichText::new(label)
+                    };
+                    if ui.selectable_label(current, text).clicked() && !current {
+                        go_to = Some(index);
+                    }
+                }
+                if let Some(index) = go_to {
+                    let snapshot = self.history.go_to(index).clone();
+                    self.restore(Some(snapshot));
+                }
+            });
+        });
+
+        // continuous edits like dragging a value or typing a name only become one entry once they are done
+        let editing = ctx.input(|input| input.pointer.any_down()) || ctx.wants_keyboard_input();
+        let history_state = self.history.state();
+        if !editing
+            && (self.camera != history_state.camera
+                || self.groups != history_state.groups
+                || self.primitives != history_state.primitives
+                || self.lights != history_state.lights)
+        {
+            let snapshot = SceneSnapshot {
+                camera: self.camera.clone(),
+                groups: self.groups.clone(),
+                primitives: self.primitives.clone(),
+                lights: self.lights.clone(),
+            };
+            let label = history_state.edit_label(&snapshot);
+            self.history.record(label, snapshot);
+        }
+
+        egui::TopBottomPanel::bottom("Status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let seconds = self.render_time.as_secs_f32();
+                let image_size = self.main_texture.size();
+                let pixel_count = image_size.width as f32 * image_size.height as f32;
+                ui.label(if self.tiled_render.is_some() {
+                    "Paused for the tiled render"
+                } else if finished {
+                    "Finished"
+                } else if self.previewing {
+                    "Previewing"
+                } else {
+                    "Rendering"
+                });
+                ui.separator();
+                // with adaptive sampling the pixels that converged stop taking samples, so the mean
+                // comes from what they actually took
+                let estimate = self.statistics.estimate.filter(|_| self.frame_count > 0);
+                let mean_samples_per_pixel = estimate
+                    .map_or(samples_per_pixel as f32, |estimate| {
+                        estimate.samples_per_pixel
+                    });
+                ui.label(format!("{mean_samples_per_pixel:.1} samples per pixel"))
+                    .on_hover_text(format!("At most {samples_per_pixel}"));
+                ui.separator();
+                ui.label(format!("{seconds:.1}s"));
+                ui.separator();
+                if seconds > 0.0 {
+                    ui.label(format!(
+                        "{:.1} samples per pixel per second, {:.1}M samples per second",
+                        mean_samples_per_pixel / seconds,
+                        mean_samples_per_pixel * pixel_count / seconds / 1e6,
+                    ));
+                    ui.separator();
+                }
+                if let Some(estimate) = estimate {
+                    ui.label(format!("Noise: {:.2}%", estimate.relative_error * 100.0))
+                        .on_hover_text(
+                            "The mean standard error of the pixels relative to their brightness",
+                        );
+                }
+            });
+        });
+
         egui::CentralPanel::default()
             .frame(egui::Frame::none().fill(egui::Color32::from_rgb(255, 0, 255)))
             .show(ctx, |ui| {
@@ -441,17 +3372,11 @@ impl eframe::App for App {
                     ..
                 } = frame.wgpu_render_state().unwrap();
 
-                let limits = device.limits();
-                let available_size = ui.available_size().clamp(
-                    egui::Vec2::ZERO,
-                    egui::vec2(
-                        limits.max_texture_dimension_2d as _,
-                        limits.max_texture_dimension_2d as _,
-                    ),
-                );
-                let (rect, _response) = ui.allocate_exact_size(available_size, egui::Sense::drag());
-                let width = rect.width() as u32;
-                let height = rect.height() as u32;
+                let (rect, response) =
+                    ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
+                let (width, height, image_rect) = self
+                    .resolution
+                    .layout(rect, device.limits().max_texture_dimension_2d);
 
                 let old_image_size = self.main_texture.size();
                 if width > 0
@@ -478,9 +3403,10 @@ impl eframe::App for App {
                     renderer.write().update_egui_texture_from_wgpu_texture(
                         device,
                         &texture_view,
-                        wgpu::FilterMode::Nearest,
+                        self.resolution.filter,
                         self.texture_id,
                     );
+                    self.texture_filter_mode = self.resolution.filter;
 
                     self.texture_bind_group =
                         device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -509,94 +3435,297 @@ impl eframe::App for App {
                     let main_texture_view = self
                         .main_texture
                         .create_view(&wgpu::TextureViewDescriptor::default());
+                    self.normal_texture = device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("Normal Texture"),
+                        size: self.main_texture.size(),
+                        mip_level_count: self.main_texture.mip_level_count(),
+                        sample_count: self.main_texture.sample_count(),
+                        dimension: self.main_texture.dimension(),
+                        format: self.main_texture.format(),
+                        usage: self.main_texture.usage(),
+                        view_formats: &[],
+                    });
+                    let normal_texture_view = self
+                        .normal_texture
+                        .create_view(&wgpu::TextureViewDescriptor::default());
+                    self.albedo_depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("Albedo Depth Texture"),
+                        size: self.main_texture.size(),
+                        mip_level_count: self.main_texture.mip_level_count(),
+                        sample_count: self.main_texture.sample_count(),
+                        dimension: self.main_texture.dimension(),
+                        format: self.main_texture.format(),
+                        usage: self.main_texture.usage(),
+                        view_formats: &[],
+                    });
+                    let albedo_depth_texture_view = self
+                        .albedo_depth_texture
+                        .create_view(&wgpu::TextureViewDescriptor::default());
+                    self.aov_texture = device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("AOV Texture"),
+                        size: self.main_texture.size(),
+                        mip_level_count: self.main_texture.mip_level_count(),
+                        sample_count: self.main_texture.sample_count(),
+                        dimension: self.main_texture.dimension(),
+                        format: self.main_texture.format(),
+                        usage: self.main_texture.usage(),
+                        view_formats: &[],
+                    });
+                    let aov_texture_view = self
+                        .aov_texture
+                        .create_view(&wgpu::TextureViewDescriptor::default());
+                    self.moment_texture = device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("Moment Texture"),
+                        size: self.main_texture.size(),
+                        mip_level_count: self.main_texture.mip_level_count(),
+                        sample_count: self.main_texture.sample_count(),
+                        dimension: self.main_texture.dimension(),
+                        format: self.main_texture.format(),
+                        usage: self.main_texture.usage(),
+                        view_formats: &[],
+                    });
+                    let moment_texture_view = self
+                        .moment_texture
+                        .create_view(&wgpu::TextureViewDescriptor::default());
 
                     self.main_texture_bind_group =
                         device.create_bind_group(&wgpu::BindGroupDescriptor {
                             label: Some("Main Texture Bind Group"),
                             layout: &self.main_texture_bind_group_layout,
+                            entries: &[
+                                wgpu::BindGroupEntry {
+                                    binding: 0,
+                                    resource: wgpu::BindingResource::TextureView(
+                                        &main_texture_view,
+                                    ),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 1,
+                                    resource: wgpu::BindingResource::TextureView(
+                                        &normal_texture_view,
+                                    ),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 2,
+                                    resource: wgpu::BindingResource::TextureView(
+                                        &albedo_depth_texture_view,
+                                    ),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 3,
+                                    resource: wgpu::BindingResource::TextureView(&aov_texture_view),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 4,
+                                    resource: wgpu::BindingResource::TextureView(
+                                        &moment_texture_view,
+                                    ),
+                                },
+                            ],
+                        });
+                    self.main_display_texture_bind_group =
+                        device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("Main Display Texture Bind Group"),
+                            layout: &self.display_texture_bind_group_layout,
                             entries: &[wgpu::BindGroupEntry {
                                 binding: 0,
                                 resource: wgpu::BindingResource::TextureView(&main_texture_view),
                             }],
                         });
 
+                    self.denoiser.resize(
+                        device,
+                        &self.display_texture_bind_group_layout,
+                        width,
+                        height,
+                    );
+                    self.bloom.resize(
+                        device,
+                        &self.display_texture_bind_group_layout,
+                        width,
+                        height,
+                    );
+                    self.statistics.resize(device, width, height);
+
+                    self.frame_count = 0;
+                }
+
+                // anything that restarted the accumulation counts as an edit
+                if self.frame_count == 0 && frame_count_before_ui != 0 {
+                    self.last_edit = Some(std::time::Instant::now());
+                }
+                let previewing = self.camera.preview.enabled
+                    && self.last_edit.is_some_and(|last_edit| {
+                        last_edit.elapsed().as_secs_f32() < self.camera.preview.delay
+                    });
+                if self.previewing != previewing {
+                    self.previewing = previewing;
                     self.frame_count = 0;
                 }
 
                 {
                     let mut buffer = UniformBuffer::new([0; GpuCamera::SHADER_SIZE.get() as _]);
-                    let Camera {
-                        position,
-                        fov,
-                        up_sky_color,
-                        down_sky_color,
-                        bounce_count,
-                        sample_count,
-                    } = self.camera;
                     buffer
-                        .write(&GpuCamera {
-                            position,
-                            tan_half_fov: f32::tan(fov.to_radians() / 2.0),
-                            up_sky_color,
-                            down_sky_color,
-                            bounce_count,
-                            sample_count,
-                            seed_offset: rand::random(),
-                            frame_count: self.frame_count,
-                        })
+                        .write(&self.camera.gpu_camera(
+                            self.frame_count,
+                            self.previewing,
+                            cgmath::vec2(width, height),
+                            cgmath::vec2(0, 0),
+                        ))
                         .unwrap();
                     queue.write_buffer(&self.camera_uniform_buffer, 0, &buffer.into_inner());
                 }
 
                 {
-                    let gpu_hyper_spheres = GpuHyperSpheres {
+                    let mut buffer = UniformBuffer::new([0; GpuView::SHADER_SIZE.get() as _]);
+                    buffer.write(&self.view.gpu_view()).unwrap();
+                    queue.write_buffer(&self.view_uniform_buffer, 0, &buffer.into_inner());
+                }
+
+                // the frame count may have been reset above, and the tiled render needs the camera uniform
+                let finished = (finished && self.frame_count != 0) || self.tiled_render.is_some();
+                if !finished {
+                    self.denoiser.prepare(
+                        queue,
+                        &self.denoiser_settings,
+                        self.camera.position,
+                        f32::tan(self.camera.fov.to_radians() / 2.0),
+                    );
+                }
+
+                if self.bloom_settings.enabled {
+                    self.bloom.prepare(queue, &self.bloom_settings);
+                }
+
+                let mut scene_buffers_resized = false;
+                // the indices the shaders give the objects count through the kinds in order
+                self.uploaded_objects.clear();
+                for (kind, primitive_kind) in self.primitive_kinds.iter().enumerate() {
+                    // the shaders only know about world space
+                    let visible_primitives = self.primitives[kind]
+                        .iter()
+                        .filter(|primitive| {
+                            !self.is_node_hidden(NodeId::Primitive(kind, primitive.node().id))
+                        })
+                        .map(|primitive| {
+                            let transform = group_transform(&self.groups, primitive.node().parent)
+                                .then(primitive.node().local_transform());
+                            (transform, primitive.as_ref())
+                        })
+                        .collect::<Vec<_>>();
+                    scene_buffers_resized |= primitive_kind.upload(
+                        device,
+                        queue,
+                        &mut self.primitive_storage_buffers[kind],
+                        &visible_primitives,
+                    );
+                    self.uploaded_objects.extend(
+                        visible_primitives
+                            .iter()
+                            .map(|(_, primitive)| NodeId::Primitive(kind, primitive.node().id)),
+                    );
+                }
+
+                {
+                    let selected_groups = self
+                        .selection
+                        .iter()
+                        .filter_map(|node| match node {
+                            NodeId::Group(id) => Some(*id),
+                            NodeId::Primitive(..) => None,
+                        })
+                        .collect::<Vec<_>>();
+                    let selected_objects = self
+                        .uploaded_objects
+                        .iter()
+                        .map(|&node| {
+                            let selected = self.selection.contains(&node)
+                                || self.node_transform(node).is_some_and(|(parent, _)| {
+                                    any_group(&self.groups, parent, |group| {
+                                        selected_groups.contains(&group.id)
+                                    })
+                                });
+                            u32::from(selected)
+                        })
+                        .collect::<Vec<_>>();
+
+                    let mut buffer = StorageBuffer::new(Vec::<u8>::new());
+                    buffer.write(&selected_objects).unwrap();
+                    let buffer = buffer.into_inner();
+
+                    let new_size = buffer.len().try_into().unwrap();
+                    if self.selected_objects_storage_buffer.size() < new_size {
+                        self.selected_objects_storage_buffer =
+                            device.create_buffer(&wgpu::BufferDescriptor {
+                                label: Some("Selected Objects Storage Buffer"),
+                                size: new_size,
+                                usage: self.selected_objects_storage_buffer.usage(),
+                                mapped_at_creation: false,
+                            });
+                        self.view_bind_group = create_view_bind_group(
+                            device,
+                            &self.view_bind_group_layout,
+                            &self.view_uniform_buffer,
+                            &self.selected_objects_storage_buffer,
+                        );
+                    }
+
+                    queue.write_buffer(&self.selected_objects_storage_buffer, 0, &buffer);
+                }
+
+                {
+                    let gpu_lights = GpuLights {
                         count: ArrayLength,
                         data: &self
-                            .hyper_spheres
+                            .lights
                             .iter()
                             .map(
-                                |&HyperSphere {
+                                |&Light {
                                      name: _,
                                      id: _,
+                                     kind,
                                      position,
+                                     direction,
                                      color,
-                                     radius,
-                                 }| GpuHyperSphere {
+                                     intensity,
+                                 }| GpuLight {
+                                    kind: kind.gpu_kind(),
                                     position,
+                                    direction,
                                     color,
-                                    radius,
+                                    intensity,
                                 },
                             )
                             .collect::<Vec<_>>(),
                     };
 
-                    let mut buffer = StorageBuffer::new(Vec::<u8>::with_capacity(
-                        gpu_hyper_spheres.size().get() as _,
-                    ));
-                    buffer.write(&gpu_hyper_spheres).unwrap();
+                    let mut buffer =
+                        StorageBuffer::new(Vec::<u8>::with_capacity(gpu_lights.size().get() as _));
+                    buffer.write(&gpu_lights).unwrap();
                     let buffer = buffer.into_inner();
 
                     let new_size = buffer.len().try_into().unwrap();
-                    if self.hyper_spheres_storage_buffer.size() < new_size {
-                        self.hyper_spheres_storage_buffer =
+                    if self.lights_storage_buffer.size() < new_size {
+                        self.lights_storage_buffer =
                             device.create_buffer(&wgpu::BufferDescriptor {
-                                label: Some("Hyper Spheres Storage Buffer"),
+                                label: Some("Lights Storage Buffer"),
                                 size: new_size,
-                                usage: self.hyper_spheres_storage_buffer.usage(),
+                                usage: self.lights_storage_buffer.usage(),
                                 mapped_at_creation: false,
                             });
-                        self.hyper_spheres_bind_group =
-                            device.create_bind_group(&wgpu::BindGroupDescriptor {
-                                label: Some("Hyper Spheres Bind Group"),
-                                layout: &self.hyper_spheres_bind_group_layout,
-                                entries: &[wgpu::BindGroupEntry {
-                                    binding: 0,
-                                    resource: self.hyper_spheres_storage_buffer.as_entire_binding(),
-                                }],
-                            });
+                        scene_buffers_resized = true;
                     }
 
-                    queue.write_buffer(&self.hyper_spheres_storage_buffer, 0, &buffer);
+                    queue.write_buffer(&self.lights_storage_buffer, 0, &buffer);
+                }
+
+                if scene_buffers_resized {
+                    self.scene_bind_group = create_scene_bind_group(
+                        device,
+                        &self.scene_bind_group_layout,
+                        &self.lights_storage_buffer,
+                        &self.primitive_storage_buffers,
+                    );
                 }
 
                 let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -613,28 +3742,307 @@ impl eframe::App for App {
                             label: Some("Compute pass"),
                             timestamp_writes: None,
                         });
-                    compute_pass.set_pipeline(&self.raytracing_pipeline);
-                    compute_pass.set_bind_group(0, &self.main_texture_bind_group, &[]);
-                    compute_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-                    compute_pass.set_bind_group(2, &self.hyper_spheres_bind_group, &[]);
-                    compute_pass.dispatch_workgroups(dispatch_with as _, dispatch_height as _, 1);
+                    // once finished only the view is updated, the accumulated image stays as it is
+                    if !finished {
+                        compute_pass.set_pipeline(&self.raytracing_pipeline);
+                        compute_pass.set_bind_group(0, &self.main_texture_bind_group, &[]);
+                        compute_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                        compute_pass.set_bind_group(2, &self.scene_bind_group, &[]);
+                        compute_pass.dispatch_workgroups(
+                            dispatch_with as _,
+                            dispatch_height as _,
+                            1,
+                        );
+                    }
+
+                    let display_bind_group = if !self.denoiser_settings.enabled {
+                        &self.main_display_texture_bind_group
+                    } else if finished {
+                        self.denoiser.display_bind_group(&self.denoiser_settings)
+                    } else {
+                        self.denoiser.encode(
+                            &mut compute_pass,
+                            &self.main_texture_bind_group,
+                            &self.denoiser_settings,
+                            self.frame_count,
+                            (dispatch_with, dispatch_height),
+                        )
+                    };
+                    let display_bind_group = if self.bloom_settings.enabled {
+                        self.bloom
+                            .encode(&mut compute_pass, display_bind_group, (width, height))
+                    } else {
+                        display_bind_group
+                    };
 
                     compute_pass.set_pipeline(&self.texture_copy_pipeline);
                     compute_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-                    compute_pass.set_bind_group(1, &self.main_texture_bind_group, &[]);
+                    compute_pass.set_bind_group(1, display_bind_group, &[]);
+                    compute_pass.set_bind_group(2, &self.main_texture_bind_group, &[]);
+                    compute_pass.set_bind_group(3, &self.view_bind_group, &[]);
                     compute_pass.dispatch_workgroups(dispatch_with as _, dispatch_height as _, 1);
                 }
                 queue.submit(std::iter::once(encoder.finish()));
-                self.frame_count += 1;
+                if !finished && self.frame_count == 0 {
+                    self.statistics.restart();
+                }
+                self.statistics.update(
+                    device,
+                    queue,
+                    &self.main_texture_bind_group,
+                    (width, height),
+                    self.camera.sample_count,
+                    !finished,
+                );
+                if !finished {
+                    if self.frame_count == 0 {
+                        self.render_start = std::time::Instant::now();
+                    }
+                    self.frame_count += 1;
+                    self.render_time = self.render_start.elapsed();
+                }
+
+                if let Some(tiled_render) = &mut self.tiled_render {
+                    let mut buffer = UniformBuffer::new([0; GpuCamera::SHADER_SIZE.get() as _]);
+                    buffer
+                        .write(&self.camera.gpu_camera(
+                            tiled_render.frame_count(),
+                            false,
+                            tiled_render.image_size(),
+                            tiled_render.tile().offset,
+                        ))
+                        .unwrap();
+                    queue.write_buffer(&self.camera_uniform_buffer, 0, &buffer.into_inner());
+
+                    let result = tiled_render.render_frame(
+                        device,
+                        queue,
+                        &TilePipelines {
+                            raytracing_pipeline: &self.raytracing_pipeline,
+                            camera_bind_group: &self.camera_bind_group,
+                            scene_bind_group: &self.scene_bind_group,
+                            texture_copy_pipeline: &self.texture_copy_pipeline,
+                        },
+                    );
+                    match result {
+                        Ok(false) => ui.ctx().request_repaint(),
+                        Ok(true) => {
+                            let path = &self.tiled_render_settings.path;
+                            self.tiled_render_status =
+                                Some(match self.tiled_render.take().unwrap().finish() {
+                                    Ok(()) => format!("Saved to {path}"),
+                                    Err(error) => format!("Error: {error}"),
+                                });
+                        }
+                        Err(error) => {
+                            // the error is what matters, not whether the unfinished file is gone
+                            let _ = self.tiled_render.take().unwrap().cancel();
+                            self.tiled_render_status = Some(format!("Error: {error}"));
+                        }
+                    }
+                }
+
+                if self.texture_filter_mode != self.resolution.filter {
+                    let texture_view = self
+                        .texture
+                        .create_view(&wgpu::TextureViewDescriptor::default());
+                    renderer.write().update_egui_texture_from_wgpu_texture(
+                        device,
+                        &texture_view,
+                        self.resolution.filter,
+                        self.texture_id,
+                    );
+                    self.texture_filter_mode = self.resolution.filter;
+                }
 
+                ui.painter().rect_filled(rect, 0.0, egui::Color32::BLACK);
                 ui.painter().image(
                     self.texture_id,
-                    rect,
+                    image_rect,
                     egui::Rect::from_min_max(egui::pos2(0.0, 1.0), egui::pos2(1.0, 0.0)),
                     egui::Color32::WHITE,
                 );
-            });
 
-        ctx.request_repaint();
+                if let Some((parent, local_transform)) = self
+                    .selection
+                    .last()
+                    .filter(|&&node| !self.is_node_hidden(node) && !self.is_node_locked(node))
+                    .and_then(|&node| self.node_transform(node))
+                {
+                    let camera = &self.camera;
+                    // the image is drawn upside down
+                    let project = |point| {
+                        let uv = camera.project(point, image_rect.aspect_ratio())?;
+                        Some(image_rect.lerp_inside(egui::vec2(uv.x, 1.0 - uv.y)))
+                    };
+                    // the gizmo works in world space, so moving along x moves along x whatever the groups do
+                    let before = group_transform(&self.groups, parent).then(local_transform);
+                    let mut after = before;
+                    if self.gizmo.show(ui, &response, &project, &mut after) {
+                        // everything else that is selected moves and turns around the last selected node
+                        let change = after.then(before.inverse());
+                        for node in self.selection_roots() {
+                            if self.is_node_locked(node) {
+                                continue;
+                            }
+                            let Some((parent, local_transform)) = self.node_transform(node) else {
+                                continue;
+                            };
+                            let parent_transform = group_transform(&self.groups, parent);
+                            let transform = parent_transform
+                                .inverse()
+                                .then(change.then(parent_transform.then(local_transform)));
+                            self.set_node_transform(node, transform);
+                        }
+                        self.frame_count = 0;
+                    }
+                }
+
+                if let Some(pointer) = response.interact_pointer_pos().filter(|&pointer| {
+                    response.clicked() && !self.gizmo.is_hovered() && image_rect.contains(pointer)
+                }) {
+                    // the image is drawn upside down
+                    let uv = (pointer - image_rect.min) / image_rect.size();
+                    let direction = self
+                        .camera
+                        .ray_direction(cgmath::vec2(uv.x, 1.0 - uv.y), image_rect.aspect_ratio());
+                    let hit = self
+                        .all_primitives()
+                        .filter(|&(node, _)| {
+                            !self.is_node_hidden(node) && !self.is_node_locked(node)
+                        })
+                        .filter_map(|(node, primitive)| {
+                            let transform = group_transform(&self.groups, primitive.node().parent)
+                                .then(primitive.node().local_transform());
+                            // the box is cheaper to miss
+                            if !primitive
+                                .bounding_box(transform)
+                                .is_hit(self.camera.position, direction)
+                            {
+                                return None;
+                            }
+                            Some((
+                                node,
+                                primitive.intersect(transform, self.camera.position, direction)?,
+                            ))
+                        })
+                        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                        .map(|(node, _)| node);
+                    let modifiers = ui.input(|input| input.modifiers);
+                    match hit {
+                        // there is no order to select a range in here, so shift adds like ctrl
+                        Some(node) if modifiers.shift => {
+                            self.select(node, egui::Modifiers::COMMAND)
+                        }
+                        Some(node) => self.select(node, modifiers),
+                        None if modifiers.shift || modifiers.command => {}
+                        None => self.selection.clear(),
+                    }
+                    self.reveal_selected_node = true;
+                    ui.ctx().request_repaint();
+                }
+
+                if let Some(pointer) = response.hover_pos().filter(|&pointer| {
+                    self.view.pixel_inspector
+                        && width > 0
+                        && height > 0
+                        && image_rect.contains(pointer)
+                }) {
+                    // the image is drawn upside down
+                    let uv = (pointer - image_rect.min) / image_rect.size();
+                    let x = ((uv.x * width as f32) as u32).min(width - 1);
+                    let y = (((1.0 - uv.y) * height as f32) as u32).min(height - 1);
+                    // the radiance keeps changing while the frames accumulate
+                    let pixel = (x, y, self.render_start, self.frame_count);
+
+                    if let Some((read_pixel, readback)) = &self.texel_readback {
+                        if let Some(data) = readback.try_read(device) {
+                            let texels = data
+                                .map(|data| texels_from_bytes(&data))
+                                .map_err(anyhow::Error::from);
+                            self.inspected_texels = Some((*read_pixel, texels));
+                            self.texel_readback = None;
+                        }
+                    }
+                    let inspected_pixels = [
+                        self.inspected_texels.as_ref().map(|(pixel, _)| *pixel),
+                        self.texel_readback.as_ref().map(|(pixel, _)| *pixel),
+                    ];
+                    if self.texel_readback.is_none() && !inspected_pixels.contains(&Some(pixel)) {
+                        let readback = read_texels(
+                            device,
+                            queue,
+                            [
+                                &self.main_texture,
+                                &self.normal_texture,
+                                &self.albedo_depth_texture,
+                                &self.aov_texture,
+                            ],
+                            x,
+                            y,
+                        );
+                        self.texel_readback = Some((pixel, readback));
+                    }
+                    if self.texel_readback.is_some() {
+                        ui.ctx().request_repaint();
+                    }
+
+                    response.on_hover_ui_at_pointer(|ui| match &self.inspected_texels {
+                        None => {
+                            ui.label(format!("Pixel: {x}, {y}"));
+                        }
+                        Some(((x, y, _, _), Ok([color, normal, albedo_depth, aov]))) => {
+                            let (x, y) = (*x, *y);
+                            ui.label(format!("Pixel: {x}, {y}"));
+                            ui.label(format!(
+                                "Radiance: {:.3}, {:.3}, {:.3}",
+                                color.x, color.y, color.z
+                            ));
+                            let distance = albedo_depth.w;
+                            let primitive = self
+                                .uploaded_objects
+                                .get((aov.x as usize).wrapping_sub(1))
+                                .and_then(|&node| match node {
+                                    NodeId::Primitive(kind, id) => self.primitive(kind, id),
+                                    NodeId::Group(_) => None,
+                                });
+                            match primitive {
+                                Some(primitive) if distance > 0.0 => {
+                                    // through the middle of the pixel, which the samples of the shader are jittered around
+                                    let direction = self.camera.ray_direction(
+                                        cgmath::vec2(
+                                            x as f32 / width as f32,
+                                            y as f32 / height as f32,
+                                        ),
+                                        width as f32 / height as f32,
+                                    );
+                                    let position = self.camera.position + direction * distance;
+                                    let normal = normal.normalize();
+                                    ui.label(format!("Object: {}", primitive.node().name));
+                                    ui.label(format!(
+                                        "Position: {:.3}, {:.3}, {:.3}, {:.3}",
+                                        position.x, position.y, position.z, position.w
+                                    ));
+                                    ui.label(format!(
+                                        "Normal: {:.3}, {:.3}, {:.3}, {:.3}",
+                                        normal.x, normal.y, normal.z, normal.w
+                                    ));
+                                    ui.label(format!("Distance: {distance:.3}"));
+                                }
+                                _ => {
+                                    ui.label("Object: Sky");
+                                }
+                            }
+                        }
+                        Some((_, Err(error))) => {
+                            ui.label(format!("Error: {error}"));
+                        }
+                    });
+                }
+
+                if !finished {
+                    ui.ctx().request_repaint();
+                }
+            });
     }
 }
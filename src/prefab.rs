@@ -0,0 +1,96 @@
+use crate::primitive::PrimitiveKind;
+use crate::scene::SceneFile;
+use std::path::PathBuf;
+
+pub(crate) struct Prefab {
+    pub(crate) name: String,
+    /// Placed relative to where an instance of it goes
+    pub(crate) file: SceneFile,
+}
+
+/// Arrangements of objects that are kept on disk as scene files, one per prefab, to be reused between scenes
+pub(crate) struct PrefabLibrary {
+    pub(crate) directory: String,
+    pub(crate) prefabs: Vec<Prefab>,
+}
+
+impl Default for PrefabLibrary {
+    fn default() -> Self {
+        Self {
+            directory: "prefabs".into(),
+            prefabs: vec![],
+        }
+    }
+}
+
+/// An instance of a prefab inside of itself would grow every time its instances get updated
+fn check_not_recursive(name: &str, file: &SceneFile) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !file
+            .groups
+            .iter()
+            .any(|group| group.prefab.as_deref() == Some(name)),
+        "the prefab {name} cannot contain an instance of itself"
+    );
+    Ok(())
+}
+
+impl PrefabLibrary {
+    fn path(&self, name: &str) -> PathBuf {
+        PathBuf::from(&self.directory).join(format!("{name}.ron"))
+    }
+
+    /// Reads every prefab in the directory again, a directory that does not exist yet is just empty
+    pub(crate) fn reload(&mut self, kinds: &[PrimitiveKind]) -> anyhow::Result<()> {
+        self.prefabs.clear();
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "ron") {
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let file = SceneFile::from_text(&std::fs::read_to_string(&path)?, kinds)
+                    .and_then(|file| check_not_recursive(name, &file).map(|()| file))
+                    .map_err(|error| error.context(format!("in {}", path.display())))?;
+                self.prefabs.push(Prefab {
+                    name: name.into(),
+                    file,
+                });
+            }
+        }
+        self.prefabs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    /// Writes the prefab to disk, replacing any with the same name
+    pub(crate) fn save(
+        &mut self,
+        name: &str,
+        file: SceneFile,
+        kinds: &[PrimitiveKind],
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !name.is_empty() && !name.contains(['/', '\\']),
+            "invalid prefab name {name:?}"
+        );
+        check_not_recursive(name, &file)?;
+        std::fs::create_dir_all(&self.directory)?;
+        std::fs::write(self.path(name), file.to_text(kinds))?;
+        self.prefabs.retain(|prefab| prefab.name != name);
+        self.prefabs.push(Prefab {
+            name: name.into(),
+            file,
+        });
+        self.prefabs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.iter().find(|prefab| prefab.name == name)
+    }
+}
@@ -0,0 +1,530 @@
+//! The kinds of objects the raytracer can render, each with its own storage buffer in the scene bind group
+
+use crate::scene::Transform;
+use cgmath::Vector4;
+use eframe::{egui, wgpu};
+use encase::internal::WriteInto;
+use encase::{ArrayLength, ShaderSize, ShaderType, StorageBuffer};
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use std::any::Any;
+use std::num::NonZeroU64;
+
+/// An axis aligned box around something in world space
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingBox4D {
+    pub min: Vector4<f32>,
+    pub max: Vector4<f32>,
+}
+
+impl BoundingBox4D {
+    /// Whether the ray passes through the box in front of its origin
+    pub fn is_hit(&self, origin: Vector4<f32>, direction: Vector4<f32>) -> bool {
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..4 {
+            // dividing by zero gives infinities that still compare the right way
+            let inverse_direction = 1.0 / direction[axis];
+            let t0 = (self.min[axis] - origin[axis]) * inverse_direction;
+            let t1 = (self.max[axis] - origin[axis]) * inverse_direction;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        near <= far
+    }
+}
+
+/// What every primitive has as a node of the scene tree, whatever its kind
+#[derive(Clone, PartialEq, Debug)]
+pub struct Node {
+    pub name: String,
+    /// Unique among the primitives of the same kind
+    pub id: usize,
+    /// The group this is inside of, `None` at the top level
+    pub parent: Option<usize>,
+    /// Relative to the parent
+    pub position: Vector4<f32>,
+    pub rotation: cgmath::Matrix4<f32>,
+    /// Left out of the render without being deleted
+    pub hidden: bool,
+    /// Cannot be picked in the viewport or edited
+    pub locked: bool,
+}
+
+impl Node {
+    pub fn local_transform(&self) -> Transform {
+        Transform {
+            position: self.position,
+            rotation: self.rotation,
+        }
+    }
+}
+
+/// Something the raytracer can render. `WGSL` gets imported into the raytracing shader as the module
+/// `NAME`, and the primitives of the kind are in `LIST_NAME`, an array of `GPU_TYPE` at its binding
+pub trait Primitive4D: Clone + PartialEq + Serialize + DeserializeOwned + 'static {
+    const NAME: &'static str;
+    /// What the list of them is called in scene files and in WGSL
+    const LIST_NAME: &'static str;
+    /// What the UI calls one of them
+    const LABEL: &'static str;
+    /// The struct `Gpu` is in WGSL
+    const GPU_TYPE: &'static str;
+    /// Defines `GPU_TYPE` and `fn intersect_<NAME>(ray: Ray, primitive: GPU_TYPE) -> Hit`, which can leave
+    /// the index fields of the hit alone
+    const WGSL: &'static str;
+    /// Whether next event estimation samples the ones that glow, then `WGSL` also defines
+    /// `fn <NAME>_emission(primitive: GPU_TYPE) -> vec3<f32>`,
+    /// `fn sample_<NAME>_light(primitive: GPU_TYPE, origin: vec4<f32>, state: ptr<function, RandomState>) -> vec4<f32>`,
+    /// which picks a point on it, and `fn <NAME>_light_pdf(primitive: GPU_TYPE, origin: vec4<f32>, point: vec4<f32>) -> f32`,
+    /// the solid angle pdf of picking `point` as seen from `origin`
+    const IS_LIGHT: bool = false;
+
+    type Gpu: ShaderType + ShaderSize + WriteInto;
+
+    /// A new one with the default properties, for the "New" buttons
+    fn new(node: Node) -> Self;
+
+    fn node(&self) -> &Node;
+
+    fn node_mut(&mut self) -> &mut Node;
+
+    /// Where it is in world space when its parent groups place it at `transform`
+    fn bounding_box(&self, transform: Transform) -> BoundingBox4D;
+
+    /// The distance along the ray to the hit, with the same rules as the WGSL version
+    fn intersect(
+        &self,
+        transform: Transform,
+        origin: Vector4<f32>,
+        direction: Vector4<f32>,
+    ) -> Option<f32>;
+
+    /// What goes in the storage buffer, which only knows about world space
+    fn gpu(&self, transform: Transform) -> Self::Gpu;
+
+    /// The properties that are not about where it is, which several can share. Returns whether any of them changed
+    fn properties_ui(&mut self, ui: &mut egui::Ui) -> bool;
+
+    /// Takes over the properties that `edited` changed compared to `before`, when several are edited at once
+    fn copy_changed_properties(&mut self, before: &Self, edited: &Self);
+}
+
+/// A primitive whose kind is only known at runtime, which every `Primitive4D` is
+pub trait DynPrimitive: Any {
+    fn node(&self) -> &Node;
+
+    fn node_mut(&mut self) -> &mut Node;
+
+    fn bounding_box(&self, transform: Transform) -> BoundingBox4D;
+
+    fn intersect(
+        &self,
+        transform: Transform,
+        origin: Vector4<f32>,
+        direction: Vector4<f32>,
+    ) -> Option<f32>;
+
+    fn properties_ui(&mut self, ui: &mut egui::Ui) -> bool;
+
+    /// Does nothing when `before` or `edited` are of another kind
+    fn copy_changed_properties(&mut self, before: &dyn DynPrimitive, edited: &dyn DynPrimitive);
+
+    fn clone_box(&self) -> Box<dyn DynPrimitive>;
+
+    /// Primitives of different kinds are never equal
+    fn eq_dyn(&self, other: &dyn DynPrimitive) -> bool;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<P: Primitive4D> DynPrimitive for P {
+    fn node(&self) -> &Node {
+        Primitive4D::node(self)
+    }
+
+    fn node_mut(&mut self) -> &mut Node {
+        Primitive4D::node_mut(self)
+    }
+
+    fn bounding_box(&self, transform: Transform) -> BoundingBox4D {
+        Primitive4D::bounding_box(self, transform)
+    }
+
+    fn intersect(
+        &self,
+        transform: Transform,
+        origin: Vector4<f32>,
+        direction: Vector4<f32>,
+    ) -> Option<f32> {
+        Primitive4D::intersect(self, transform, origin, direction)
+    }
+
+    fn properties_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        Primitive4D::properties_ui(self, ui)
+    }
+
+    fn copy_changed_properties(&mut self, before: &dyn DynPrimitive, edited: &dyn DynPrimitive) {
+        let before = before.as_any().downcast_ref::<P>();
+        let edited = edited.as_any().downcast_ref::<P>();
+        if let (Some(before), Some(edited)) = (before, edited) {
+            Primitive4D::copy_changed_properties(self, before, edited);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn DynPrimitive> {
+        Box::new(self.clone())
+    }
+
+    fn eq_dyn(&self, other: &dyn DynPrimitive) -> bool {
+        other.as_any().downcast_ref::<P>() == Some(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn DynPrimitive> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl PartialEq for Box<dyn DynPrimitive> {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_dyn(other.as_ref())
+    }
+}
+
+fn downcast<P: Primitive4D>(primitive: &dyn DynPrimitive) -> &P {
+    primitive
+        .as_any()
+        .downcast_ref()
+        .expect("a primitive in the list of another kind")
+}
+
+#[derive(ShaderType)]
+struct GpuPrimitives<T: ShaderType + ShaderSize> {
+    count: ArrayLength,
+    #[size(runtime)]
+    data: Vec<T>,
+}
+
+/// Writes the primitives at their transforms into a storage buffer, see `PrimitiveKind::upload`
+type Upload =
+    fn(&wgpu::Device, &wgpu::Queue, &mut wgpu::Buffer, &[(Transform, &dyn DynPrimitive)]) -> bool;
+
+/// Writes the list of one kind of primitives into a scene file that is being written
+type WriteList =
+    fn(&mut ron::ser::Compound<'_, &mut Vec<u8>>, &[Box<dyn DynPrimitive>]) -> ron::Result<()>;
+
+/// Reads the list of one kind of primitives out of a scene file
+type ReadList = fn(ron::Value) -> ron::Result<Vec<Box<dyn DynPrimitive>>>;
+
+/// What the app needs to know about a kind of primitive without knowing its type. The kinds it
+/// gets are the registry everything loops over, from the shaders to the outliner
+#[derive(Clone, Copy)]
+pub struct PrimitiveKind {
+    pub(crate) name: &'static str,
+    pub(crate) list_name: &'static str,
+    pub(crate) label: &'static str,
+    gpu_type: &'static str,
+    pub(crate) wgsl: &'static str,
+    is_light: bool,
+    min_binding_size: fn() -> NonZeroU64,
+    new: fn(Node) -> Box<dyn DynPrimitive>,
+    upload: Upload,
+    write_list: WriteList,
+    read_list: ReadList,
+}
+
+impl PrimitiveKind {
+    pub const fn of<P: Primitive4D>() -> Self {
+        Self {
+            name: P::NAME,
+            list_name: P::LIST_NAME,
+            label: P::LABEL,
+            gpu_type: P::GPU_TYPE,
+            wgsl: P::WGSL,
+            is_light: P::IS_LIGHT,
+            min_binding_size: GpuPrimitives::<P::Gpu>::min_size,
+            new: |node| Box::new(P::new(node)),
+            upload: upload::<P>,
+            write_list: write_list::<P>,
+            read_list: read_list::<P>,
+        }
+    }
+
+    /// The binding of its storage buffer in the scene bind group, the lights come first
+    pub(crate) fn binding(index: usize) -> u32 {
+        index as u32 + 1
+    }
+
+    pub(crate) fn bind_group_layout_entry(&self, index: usize) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: Self::binding(index),
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: Some((self.min_binding_size)()),
+            },
+            count: None,
+        }
+    }
+
+    pub(crate) fn create_storage_buffer(&self, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Storage Buffer", self.gpu_type)),
+            size: (self.min_binding_size)().get(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub(crate) fn new_primitive(&self, node: Node) -> Box<dyn DynPrimitive> {
+        (self.new)(node)
+    }
+
+    /// Writes the primitives at their transforms into `buffer`, which gets replaced when it is too small.
+    /// Returns whether it was, since the scene bind group has to be made again then
+    pub(crate) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &mut wgpu::Buffer,
+        primitives: &[(Transform, &dyn DynPrimitive)],
+    ) -> bool {
+        (self.upload)(device, queue, buffer, primitives)
+    }
+
+    pub(crate) fn write_list(
+        &self,
+        file: &mut ron::ser::Compound<'_, &mut Vec<u8>>,
+        primitives: &[Box<dyn DynPrimitive>],
+    ) -> ron::Result<()> {
+        (self.write_list)(file, primitives)
+    }
+
+    pub(crate) fn read_list(&self, list: ron::Value) -> ron::Result<Vec<Box<dyn DynPrimitive>>> {
+        (self.read_list)(list)
+    }
+}
+
+fn upload<P: Primitive4D>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &mut wgpu::Buffer,
+    primitives: &[(Transform, &dyn DynPrimitive)],
+) -> bool {
+    let gpu_primitives = GpuPrimitives {
+        count: ArrayLength,
+        data: primitives
+            .iter()
+            .map(|(transform, primitive)| downcast::<P>(*primitive).gpu(*transform))
+            .collect::<Vec<_>>(),
+    };
+    let mut contents =
+        StorageBuffer::new(Vec::<u8>::with_capacity(gpu_primitives.size().get() as _));
+    contents.write(&gpu_primitives).unwrap();
+    let contents = contents.into_inner();
+
+    let new_size = contents.len().try_into().unwrap();
+    let resized = buffer.size() < new_size;
+    if resized {
+        *buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Storage Buffer", P::GPU_TYPE)),
+            size: new_size,
+            usage: buffer.usage(),
+            mapped_at_creation: false,
+        });
+    }
+    queue.write_buffer(buffer, 0, &contents);
+    resized
+}
+
+fn write_list<P: Primitive4D>(
+    file: &mut ron::ser::Compound<'_, &mut Vec<u8>>,
+    primitives: &[Box<dyn DynPrimitive>],
+) -> ron::Result<()> {
+    let primitives = primitives
+        .iter()
+        .map(|primitive| downcast::<P>(primitive.as_ref()))
+        .collect::<Vec<_>>();
+    file.serialize_field(P::LIST_NAME, &primitives)
+}
+
+fn read_list<P: Primitive4D>(list: ron::Value) -> ron::Result<Vec<Box<dyn DynPrimitive>>> {
+    let primitives = list.into_rust::<Vec<P>>()?;
+    Ok(primitives
+        .into_iter()
+        .map(|primitive| Box::new(primitive) as Box<dyn DynPrimitive>)
+        .collect())
+}
+
+/// The module the raytracing shader imports as `primitives`: the storage buffers of every kind,
+/// `intersect_primitives`, which finds the closest hit among all of them, and the functions next
+/// event estimation samples the primitives that glow with, by the kind and index of the primitive
+pub(crate) fn wgsl_module(kinds: &[PrimitiveKind]) -> String {
+    let mut module = String::from("#import common\n#import sampling\n");
+    for kind in kinds {
+        module += &format!("#import {}\n", kind.name);
+    }
+    for (index, kind) in kinds.iter().enumerate() {
+        let PrimitiveKind {
+            name,
+            list_name,
+            gpu_type,
+            ..
+        } = kind;
+        let binding = PrimitiveKind::binding(index);
+        module += &format!(
+            "
+struct {gpu_type}s {{
+    count: u32,
+    data: array<{gpu_type}>,
+}}
+
+@group(2)
+@binding({binding})
+var<storage, read> {list_name}: {gpu_type}s;
+
+const primitive_kind_{name}: u32 = {index}u;
+"
+        );
+    }
+    module += "
+fn intersect_primitives(ray: Ray) -> Hit {
+    var closest_hit: Hit;
+    closest_hit.hit = false;
+    var offset = 0u;
+";
+    for kind in kinds {
+        let PrimitiveKind {
+            name, list_name, ..
+        } = kind;
+        module += &format!(
+            "
+    intersection_test_count += {list_name}.count;
+    for (var i = 0u; i < {list_name}.count; i += 1u) {{
+        var hit = intersect_{name}(ray, {list_name}.data[i]);
+        if hit.hit && (!closest_hit.hit || hit.distance < closest_hit.distance) {{
+            hit.index = offset + i;
+            hit.primitive_kind = primitive_kind_{name};
+            hit.primitive_index = i;
+            closest_hit = hit;
+        }}
+    }}
+    offset += {list_name}.count;
+"
+        );
+    }
+    module += &format!(
+        "
+    return closest_hit;
+}}
+
+const primitive_kind_count: u32 = {}u;
+",
+        kinds.len()
+    );
+
+    let light_function =
+        |signature: &str, case: &dyn Fn(&PrimitiveKind) -> String, default: &str| {
+            let mut function = format!("\nfn {signature} {{\n    switch kind {{\n");
+            for (index, kind) in kinds.iter().enumerate().filter(|(_, kind)| kind.is_light) {
+                function += &format!("        case {index}u: {{ return {}; }}\n", case(kind));
+            }
+            function + &format!("        default: {{ return {default}; }}\n    }}\n}}\n")
+        };
+    module += "\n// the kinds that cannot glow have no lights to sample";
+    module += &light_function(
+        "light_primitive_count(kind: u32) -> u32",
+        &|kind| format!("{}.count", kind.list_name),
+        "0u",
+    );
+    module += &light_function(
+        "light_primitive_emission(kind: u32, index: u32) -> vec3<f32>",
+        &|kind| format!("{}_emission({}.data[index])", kind.name, kind.list_name),
+        "vec3<f32>(0.0)",
+    );
+    module += &light_function(
+        "sample_light_primitive(kind: u32, index: u32, origin: vec4<f32>, state: ptr<function, RandomState>) -> vec4<f32>",
+        &|kind| format!("sample_{}_light({}.data[index], origin, state)", kind.name, kind.list_name),
+        "origin",
+    );
+    module += &light_function(
+        "light_primitive_pdf(kind: u32, index: u32, origin: vec4<f32>, point: vec4<f32>) -> f32",
+        &|kind| {
+            format!(
+                "{}_light_pdf({}.data[index], origin, point)",
+                kind.name, kind.list_name
+            )
+        },
+        "0.0",
+    );
+    module
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hyper_sphere::HyperSphere;
+    use cgmath::InnerSpace;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn hits_are_inside_of_the_bounding_box() {
+        let hyper_sphere = HyperSphere {
+            radius: 1.5,
+            ..HyperSphere::new(Node {
+                name: "Sphere".into(),
+                id: 0,
+                parent: None,
+                position: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+                rotation: crate::scene::identity_rotation(),
+                hidden: false,
+                locked: false,
+            })
+        };
+        let transform = Transform {
+            position: cgmath::vec4(1.0, -2.0, 3.0, 0.5),
+            rotation: crate::scene::identity_rotation(),
+        };
+        let bounding_box = Primitive4D::bounding_box(&hyper_sphere, transform);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut hit_count = 0;
+        for _ in 0..10_000 {
+            let origin = Vector4::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            // aimed near the hyper sphere, so a good share of the rays hit it
+            let target = transform.position
+                + Vector4::new(
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                );
+            let direction = (target - origin).normalize();
+            let Some(distance) =
+                Primitive4D::intersect(&hyper_sphere, transform, origin, direction)
+            else {
+                continue;
+            };
+            hit_count += 1;
+            assert!(bounding_box.is_hit(origin, direction));
+            let point = origin + direction * distance;
+            for axis in 0..4 {
+                assert!(point[axis] >= bounding_box.min[axis] - 1e-4);
+                assert!(point[axis] <= bounding_box.max[axis] + 1e-4);
+            }
+        }
+        assert!(hit_count > 100);
+    }
+}
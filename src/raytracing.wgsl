@@ -1,155 +1,39 @@
+// put together from the modules in `shaders` by `shader_composer`, the features that are not
+// defined are left out
+#import camera
+#import common
+#import integrator
+#import material
+#import sampling
+#import scene
+#ifdef PREVIEW
+#import preview
+#endif
+
 @group(0)
 @binding(0)
 var texture: texture_storage_2d<rgba32float, read_write>;
 
-struct Camera {
-    position: vec4<f32>,
-    tan_half_fov: f32,
-    up_sky_color: vec3<f32>,
-    down_sky_color: vec3<f32>,
-    bounce_count: u32,
-    sample_count: u32,
-    seed_offset: u32,
-    frame_count: u32,
-}
-
-@group(1)
-@binding(0)
-var<uniform> camera: Camera;
-
-struct HyperSphere {
-    position: vec4<f32>,
-    color: vec3<f32>,
-    radius: f32,
-}
-
-struct HyperSpheres {
-    count: u32,
-    data: array<HyperSphere>,
-}
-
-@group(2)
-@binding(0)
-var<storage, read> hyper_spheres: HyperSpheres;
-
-struct Ray {
-    origin: vec4<f32>,
-    direction: vec4<f32>,
-}
-
-struct Hit {
-    hit: bool,
-    color: vec3<f32>,
-    distance: f32,
-    position: vec4<f32>,
-    normal: vec4<f32>,
-}
-
-const min_distance: f32 = 0.001;
-
-fn intersect_hyper_sphere(ray: Ray, hyper_sphere: HyperSphere) -> Hit {
-    var hit: Hit;
-    hit.hit = false;
-
-    let oc = ray.origin - hyper_sphere.position;
-    let a = dot(ray.direction, ray.direction);
-    let half_b = dot(oc, ray.direction);
-    let c = dot(oc, oc) - hyper_sphere.radius * hyper_sphere.radius;
-    let discriminant = half_b * half_b - a * c;
-
-    if discriminant < 0.0 {
-        return hit;
-    }
-
-    let sqrt_discriminant = sqrt(discriminant);
-    let t0 = (-half_b - sqrt_discriminant) / a;
-    let t1 = (-half_b + sqrt_discriminant) / a;
-
-    hit.distance = t0;
-    if hit.distance < min_distance {
-        return hit;
-    }
-
-    hit.hit = true;
-    hit.color = hyper_sphere.color;
-    hit.position = ray.origin + ray.direction * hit.distance;
-    hit.normal = (hit.position - hyper_sphere.position) / hyper_sphere.radius;
-    return hit;
-}
-
-fn sky_color(ray: Ray) -> vec3<f32> {
-    return mix(camera.down_sky_color, camera.up_sky_color, ray.direction.y * 0.5 + 0.5);
-}
-
-fn random_value(state: ptr<function, u32>) -> f32 {
-    *state = *state * 747796405u + 2891336453u;
-    var result = ((*state >> ((*state >> 28u) + 4u)) ^ *state) * 277803737u;
-    result = (result >> 22u) ^ result;
-    return f32(result) / 4294967295.0;
-}
-
-fn random_value_normal_distribution(state: ptr<function, u32>) -> f32 {
-    let theta = 2.0 * 3.1415926 * random_value(state);
-    let rho = sqrt(-2.0 * log(random_value(state)));
-    return rho * cos(theta);
-}
-
-fn random_direction(state: ptr<function, u32>) -> vec4<f32> {
-    return normalize(vec4<f32>(
-        random_value_normal_distribution(state),
-        random_value_normal_distribution(state),
-        random_value_normal_distribution(state),
-        random_value_normal_distribution(state),
-    ));
-}
-
-fn random_direction_in_hemisphere(state: ptr<function, u32>, normal: vec4<f32>) -> vec4<f32> {
-    var direction = random_direction(state);
-    if dot(direction, normal) < 0.0 {
-        direction *= -1.0;
-    }
-    return direction;
-}
-
-fn get_closest_hit(ray: Ray) -> Hit {
-    var closest_hit: Hit;
-    closest_hit.hit = false;
-
-    for (var i = 0u; i < hyper_spheres.count; i += 1u) {
-        let hit = intersect_hyper_sphere(ray, hyper_spheres.data[i]);
-        if hit.hit && (!closest_hit.hit || hit.distance < closest_hit.distance) {
-            closest_hit = hit;
-        }
-    }
-
-    return closest_hit;
-}
-
-fn trace(ray_: Ray, state: ptr<function, u32>) -> vec3<f32> {
-    var ray = ray_;
-    var incoming_light = vec3<f32>(0.0);
-    var ray_color = vec3<f32>(1.0);
-
-    for (var i = 0u; i < camera.bounce_count; i += 1u) {
-        let hit = get_closest_hit(ray);
-        if hit.hit {
-            let emissive_color = vec3<f32>(0.0);
-            let emission_strength = 0.0;
-            let base_color = hit.color;
+// the first hit 4D normal, zero for the sky
+@group(0)
+@binding(1)
+var normal_texture: texture_storage_2d<rgba32float, read_write>;
 
-            ray.origin = hit.position;
-            ray.direction = normalize(hit.normal + random_direction(state));
+// the first hit color in rgb and distance in a, zero distance for the sky
+@group(0)
+@binding(2)
+var albedo_depth_texture: texture_storage_2d<rgba32float, read_write>;
 
-            incoming_light += (emissive_color * emission_strength) * ray_color;
-            ray_color *= base_color;
-        } else {
-            incoming_light += sky_color(ray) * ray_color;
-            break;
-        }
-    }
+// the first hit object index + 1 (zero for the sky) in r, first hit w coordinate in g,
+// and the number of bounces and intersection tests per sample in b and a
+@group(0)
+@binding(3)
+var aov_texture: texture_storage_2d<rgba32float, read_write>;
 
-    return incoming_light;
-}
+// the running mean of the squared luminance of every frame in r, and how many samples this pixel has taken in g
+@group(0)
+@binding(4)
+var moment_texture: texture_storage_2d<rgba32float, read_write>;
 
 @compute
 @workgroup_size(16, 16)
@@ -163,13 +47,45 @@ fn main(
         return;
     }
 
-    var aspect = f32(size.x) / f32(size.y);
+    var moment = vec2<f32>(0.0);
+    if camera.frame_count != 0u {
+        moment = textureLoad(moment_texture, coords).rg;
+#ifdef ADAPTIVE_SAMPLING
+        if camera.adaptive_sampling_threshold > 0.0 && moment.g >= f32(camera.adaptive_sampling_min_sample_count) {
+            let mean = luminance(textureLoad(texture, coords).rgb);
+            let variance = max(moment.r - mean * mean, 0.0);
+            // every frame is one estimate of the mean, so the error shrinks with the number of frames
+            let frame_count = moment.g / f32(camera.sample_count);
+            let relative_error = sqrt(variance / frame_count) / max(mean, 0.01);
+            // converged pixels keep what they have
+            if relative_error < camera.adaptive_sampling_threshold {
+                return;
+            }
+        }
+#endif
+    }
+
+    let image_size = camera.image_size;
+    let pixel = coords + camera.tile_offset;
+    var aspect = f32(image_size.x) / f32(image_size.y);
 
-    var state: u32 = u32(coords.x + coords.y * size.x) + camera.seed_offset;
+    var state: RandomState;
+    state.pixel = pixel;
+    state.pixel_seed = hash((pixel.x + pixel.y * image_size.x) ^ hash(camera.seed));
+    state.pcg_state = u32(pixel.x + pixel.y * image_size.x) + camera.seed_offset;
 
     var color = vec3<f32>(0.0);
+    var normal = vec4<f32>(0.0);
+    var albedo_depth = vec4<f32>(0.0);
+    var object_id = 0u;
+    var hit_w = 0.0;
+    bounce_count = 0u;
+    intersection_test_count = 0u;
     for (var i = 0u; i < camera.sample_count; i += 1u) {
-        let uv = (vec2<f32>(coords) + vec2<f32>(random_value(&state), random_value(&state)) * 2.0 - 1.0) / vec2<f32>(size);
+        state.sample_index = camera.frame_count * camera.sample_count + i;
+        state.dimension = 0u;
+
+        let uv = (vec2<f32>(pixel) + vec2<f32>(random_value(&state), random_value(&state)) * 2.0 - 1.0) / vec2<f32>(image_size);
         let normalized_uv = vec2<f32>(uv.x, 1.0 - uv.y) * 2.0 - 1.0;
 
         var ray: Ray;
@@ -179,11 +95,55 @@ fn main(
         ray.direction.z *= aspect * camera.tan_half_fov;
         ray.direction = normalize(ray.direction);
 
-        color += trace(ray, &state);
+        var first_hit: Hit;
+#ifdef PREVIEW
+        if camera.preview_mode == preview_mode_off {
+            color += trace(ray, &state, &first_hit);
+        } else {
+            color += preview(ray, &state, &first_hit);
+        }
+#else
+        color += trace(ray, &state, &first_hit);
+#endif
+        if first_hit.hit {
+            normal += first_hit.normal;
+            albedo_depth += vec4<f32>(first_hit.color, first_hit.distance);
+            hit_w += first_hit.position.w;
+            // ids cannot be averaged, so this is just from the first sample
+            if i == 0u {
+                object_id = first_hit.index + 1u;
+            }
+        } else {
+            albedo_depth += vec4<f32>(1.0, 1.0, 1.0, 0.0);
+        }
     }
     color /= f32(camera.sample_count);
+    normal /= f32(camera.sample_count);
+    albedo_depth /= f32(camera.sample_count);
+    hit_w /= f32(camera.sample_count);
+    let aov = vec4<f32>(
+        f32(object_id),
+        hit_w,
+        f32(bounce_count) / f32(camera.sample_count),
+        f32(intersection_test_count) / f32(camera.sample_count),
+    );
+
+    // pixels skip frames with adaptive sampling, so they are weighted by their own sample count
+    let pixel_sample_count = moment.g + f32(camera.sample_count);
+    let weight = f32(camera.sample_count) / pixel_sample_count;
 
     let old_color = textureLoad(texture, coords).rgb;
-    let new_color = old_color + ((color - old_color) / f32(camera.frame_count + 1));
-    textureStore(texture, coords, vec4<f32>(clamp(new_color, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0));
+    let new_color = old_color + (color - old_color) * weight;
+    textureStore(texture, coords, vec4<f32>(new_color, 1.0));
+
+    let squared_luminance = luminance(color) * luminance(color);
+    textureStore(moment_texture, coords, vec4<f32>(moment.r + (squared_luminance - moment.r) * weight, pixel_sample_count, 0.0, 0.0));
+
+    let old_normal = textureLoad(normal_texture, coords);
+    textureStore(normal_texture, coords, old_normal + (normal - old_normal) * weight);
+    let old_albedo_depth = textureLoad(albedo_depth_texture, coords);
+    textureStore(albedo_depth_texture, coords, old_albedo_depth + (albedo_depth - old_albedo_depth) * weight);
+    let old_aov = textureLoad(aov_texture, coords);
+    let new_aov = old_aov + (aov - old_aov) * weight;
+    textureStore(aov_texture, coords, vec4<f32>(aov.r, new_aov.gba));
 }
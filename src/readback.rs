@@ -0,0 +1,61 @@
+//! Copying data back from the GPU without waiting for it, so the frame does not stall on the copy
+
+use eframe::wgpu;
+use std::sync::mpsc;
+
+/// A copy that was submitted and gets checked on every frame until it is done
+pub(crate) struct Readback {
+    buffer: wgpu::Buffer,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl Readback {
+    /// Submits what `record` puts into the `size` bytes large buffer it gets
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u64,
+        record: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        record(&mut encoder, &buffer);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, mapped) = mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                // nobody is waiting anymore when the readback was dropped
+                let _ = sender.send(result);
+            });
+        Self { buffer, mapped }
+    }
+
+    /// The contents of the buffer once the GPU is done with it, `None` while it is not
+    pub(crate) fn try_read(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<u8>, wgpu::BufferAsyncError>> {
+        // runs the callbacks of the copies that are done, without waiting for the rest
+        device.poll(wgpu::Maintain::Poll);
+        let result = self.mapped.try_recv();
+        match result {
+            Ok(Ok(())) => {
+                let data = self.buffer.slice(..).get_mapped_range().to_vec();
+                self.buffer.unmap();
+                Some(Ok(data))
+            }
+            Ok(Err(error)) => Some(Err(error)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(wgpu::BufferAsyncError)),
+        }
+    }
+}
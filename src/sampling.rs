@@ -0,0 +1,186 @@
+//! CPU mirror of the sampling in `raytracing.wgsl`, so the distributions it draws from can be tested
+
+use cgmath::{InnerSpace, Vector4};
+use rand::Rng;
+use std::f32::consts::PI;
+
+pub(crate) fn random_value_normal_distribution(rng: &mut impl Rng) -> f32 {
+    let theta = 2.0 * PI * rng.gen::<f32>();
+    let rho = f32::sqrt(-2.0 * f32::ln(1.0 - rng.gen::<f32>()));
+    rho * theta.cos()
+}
+
+pub(crate) fn random_direction_cosine_weighted(
+    rng: &mut impl Rng,
+    normal: Vector4<f32>,
+) -> Vector4<f32> {
+    let gaussian = Vector4::new(
+        random_value_normal_distribution(rng),
+        random_value_normal_distribution(rng),
+        random_value_normal_distribution(rng),
+        random_value_normal_distribution(rng),
+    );
+    let tangent = (gaussian - gaussian.dot(normal) * normal).normalize();
+    let radius = rng.gen::<f32>().powf(1.0 / 3.0);
+    tangent * radius + normal * f32::sqrt(f32::max(1.0 - radius * radius, 0.0))
+}
+
+pub(crate) fn cosine_weighted_pdf(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) * (3.0 / (4.0 * PI))
+}
+
+pub(crate) fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+
+    let mut result = 0;
+    let (mut v1, mut v2, mut v3) = (0u32, 0u32, 0u32);
+    for bit in 0..32 {
+        let v = match (dimension, bit) {
+            (_, 0) => 1 << 31,
+            (1, _) => v1 ^ (v1 >> 1),
+            (2, 1) => 3 << 30,
+            (2, _) => v2 ^ (v2 >> 2) ^ v1,
+            (_, 1) => 3 << 30,
+            (_, 2) => 1 << 29,
+            (_, _) => v3 ^ (v3 >> 3) ^ v2,
+        };
+        if (index >> bit) & 1 != 0 {
+            result ^= v;
+        }
+        (v3, v2, v1) = (v2, v1, v);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SAMPLE_COUNT: usize = 200_000;
+
+    fn normals() -> [Vector4<f32>; 3] {
+        [
+            Vector4::unit_y(),
+            Vector4::unit_w(),
+            Vector4::new(1.0, -2.0, 0.5, 3.0).normalize(),
+        ]
+    }
+
+    #[test]
+    fn cosine_weighted_directions_are_unit_and_in_hemisphere() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for normal in normals() {
+            for _ in 0..SAMPLE_COUNT {
+                let direction = random_direction_cosine_weighted(&mut rng, normal);
+                assert!((direction.magnitude() - 1.0).abs() < 1e-4);
+                assert!(direction.dot(normal) >= 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_weighted_directions_follow_cosine_distribution() {
+        // with p(w) proportional to cos(theta) on the 3-sphere, p(theta) is proportional to
+        // cos(theta) * sin(theta)^2, so sin(theta)^3 is uniformly distributed on [0, 1]
+        const BIN_COUNT: usize = 20;
+        // chi squared critical value for 19 degrees of freedom at p = 0.001
+        const CRITICAL_VALUE: f64 = 43.82;
+
+        let mut rng = StdRng::seed_from_u64(1);
+        for normal in normals() {
+            let mut bins = [0usize; BIN_COUNT];
+            for _ in 0..SAMPLE_COUNT {
+                let direction = random_direction_cosine_weighted(&mut rng, normal);
+                let cos_theta = direction.dot(normal).clamp(0.0, 1.0);
+                let sin_cubed_theta = (1.0 - cos_theta * cos_theta).powf(1.5);
+                bins[((sin_cubed_theta * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)] += 1;
+            }
+
+            let expected = SAMPLE_COUNT as f64 / BIN_COUNT as f64;
+            let chi_squared = bins
+                .iter()
+                .map(|&count| (count as f64 - expected).powi(2) / expected)
+                .sum::<f64>();
+            assert!(
+                chi_squared < CRITICAL_VALUE,
+                "chi squared of {chi_squared} for normal {normal:?}, bins: {bins:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cosine_weighted_directions_are_symmetric_around_normal() {
+        // the integral of cos(theta)^2 over the hemisphere is pi^2/4, divided by the 4pi/3 normalisation
+        let expected_cos_theta = 3.0 * PI / 16.0;
+
+        let mut rng = StdRng::seed_from_u64(2);
+        for normal in normals() {
+            let mean = (0..SAMPLE_COUNT)
+                .map(|_| random_direction_cosine_weighted(&mut rng, normal))
+                .sum::<Vector4<f32>>()
+                / SAMPLE_COUNT as f32;
+            let mean_cos_theta = mean.dot(normal);
+            let mean_tangent = mean - mean_cos_theta * normal;
+            assert!((mean_cos_theta - expected_cos_theta).abs() < 0.005);
+            assert!(mean_tangent.magnitude() < 0.005);
+        }
+    }
+
+    #[test]
+    fn sobol_dimensions_are_stratified() {
+        for m in 1..=10 {
+            let count = 1u32 << m;
+            for dimension in 0..4 {
+                let mut strata = (0..count)
+                    .map(|index| sobol(index, dimension) >> (32 - m))
+                    .collect::<Vec<_>>();
+                strata.sort_unstable();
+                assert_eq!(strata, (0..count).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_first_two_dimensions_form_nets() {
+        // every 2^a by 2^b grid of cells with a + b = m contains exactly one of the first 2^m points
+        for m in 1..=10 {
+            let count = 1u32 << m;
+            for a in 0..=m {
+                let b = m - a;
+                let mut cells = (0..count)
+                    .map(|index| {
+                        let x = sobol(index, 0).checked_shr(32 - a).unwrap_or(0);
+                        let y = sobol(index, 1).checked_shr(32 - b).unwrap_or(0);
+                        x << b | y
+                    })
+                    .collect::<Vec<_>>();
+                cells.sort_unstable();
+                assert_eq!(cells, (0..count).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_weighted_pdf_integrates_to_one() {
+        // uniform directions over the whole 3-sphere, which has a surface area of 2pi^2
+        let mut rng = StdRng::seed_from_u64(3);
+        let normal = Vector4::unit_y();
+        let integral = (0..SAMPLE_COUNT)
+            .map(|_| {
+                let direction = Vector4::new(
+                    random_value_normal_distribution(&mut rng),
+                    random_value_normal_distribution(&mut rng),
+                    random_value_normal_distribution(&mut rng),
+                    random_value_normal_distribution(&mut rng),
+                )
+                .normalize();
+                cosine_weighted_pdf(direction.dot(normal)) * 2.0 * PI * PI
+            })
+            .sum::<f32>()
+            / SAMPLE_COUNT as f32;
+        assert!((integral - 1.0).abs() < 0.01);
+    }
+}
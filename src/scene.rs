@@ -0,0 +1,287 @@
+use crate::primitive::{DynPrimitive, PrimitiveKind};
+use crate::Light;
+use cgmath::{Matrix, SquareMatrix};
+use serde::de::{self, DeserializeOwned};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::Path;
+
+/// A position and rotation in 4D, everything in the scene is placed by one relative to its parent
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub position: cgmath::Vector4<f32>,
+    pub rotation: cgmath::Matrix4<f32>,
+}
+
+impl Transform {
+    pub(crate) fn identity() -> Self {
+        Self {
+            position: cgmath::vec4(0.0, 0.0, 0.0, 0.0),
+            rotation: cgmath::Matrix4::identity(),
+        }
+    }
+
+    /// `local` placed inside of this transform
+    pub(crate) fn then(&self, local: Transform) -> Transform {
+        Transform {
+            position: self.position + self.rotation * local.position,
+            rotation: self.rotation * local.rotation,
+        }
+    }
+
+    pub(crate) fn inverse(&self) -> Transform {
+        // rotations are orthonormal, so transposing inverts them
+        let rotation = self.rotation.transpose();
+        Transform {
+            position: -(rotation * self.position),
+            rotation,
+        }
+    }
+}
+
+pub fn identity_rotation() -> cgmath::Matrix4<f32> {
+    cgmath::Matrix4::identity()
+}
+
+/// Moves and rotates everything inside of it as one
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Group {
+    pub(crate) name: String,
+    pub(crate) id: usize,
+    /// The group this one is inside of, `None` at the top level
+    #[serde(default)]
+    pub(crate) parent: Option<usize>,
+    pub(crate) position: cgmath::Vector4<f32>,
+    #[serde(default = "identity_rotation")]
+    pub(crate) rotation: cgmath::Matrix4<f32>,
+    /// Hides everything inside of it too
+    #[serde(default)]
+    pub(crate) hidden: bool,
+    /// Keeps everything inside of it from being edited too
+    #[serde(default)]
+    pub(crate) locked: bool,
+    /// The name of the prefab this is a linked instance of, whose contents replace what is inside of it
+    /// whenever the prefab changes
+    #[serde(default)]
+    pub(crate) prefab: Option<String>,
+}
+
+/// The text format scenes and parts of them are stored in, parents refer to the ids of the groups in the same file
+#[derive(Clone)]
+pub(crate) struct SceneFile {
+    pub(crate) groups: Vec<Group>,
+    /// One list for every kind of primitive, stored under its `LIST_NAME`
+    pub(crate) primitives: Vec<Vec<Box<dyn DynPrimitive>>>,
+    pub(crate) lights: Vec<Light>,
+}
+
+/// A field name in a scene file, which ron only reads as an identifier
+struct Key(String);
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl de::Visitor<'_> for KeyVisitor {
+            type Value = Key;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                formatter.write_str("a field name")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Key, E> {
+                Ok(Key(value.into()))
+            }
+        }
+
+        deserializer.deserialize_identifier(KeyVisitor)
+    }
+}
+
+/// Reads the fields of a scene file, the primitives by the kinds that are registered
+struct SceneFileVisitor<'a> {
+    kinds: &'a [PrimitiveKind],
+}
+
+impl<'de> de::Visitor<'de> for SceneFileVisitor<'_> {
+    type Value = SceneFile;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("a scene file")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<SceneFile, A::Error> {
+        fn read<'de, T: DeserializeOwned, A: de::MapAccess<'de>>(
+            map: &mut A,
+        ) -> Result<T, A::Error> {
+            map.next_value::<ron::Value>()?
+                .into_rust()
+                .map_err(de::Error::custom)
+        }
+
+        let mut file = SceneFile::empty(self.kinds);
+        while let Some(Key(key)) = map.next_key()? {
+            if key == "groups" {
+                file.groups = read(&mut map)?;
+            } else if key == "lights" {
+                file.lights = read(&mut map)?;
+            } else if let Some(index) = self.kinds.iter().position(|kind| kind.list_name == key) {
+                file.primitives[index] = self.kinds[index]
+                    .read_list(map.next_value()?)
+                    .map_err(de::Error::custom)?;
+            } else {
+                return Err(de::Error::custom(format!(
+                    "there is no kind of primitive called {key}"
+                )));
+            }
+        }
+        Ok(file)
+    }
+}
+
+impl SceneFile {
+    pub(crate) fn empty(kinds: &[PrimitiveKind]) -> Self {
+        Self {
+            groups: vec![],
+            primitives: vec![vec![]; kinds.len()],
+            lights: vec![],
+        }
+    }
+
+    pub(crate) fn to_text(&self, kinds: &[PrimitiveKind]) -> String {
+        let mut text = Vec::new();
+        let mut serializer =
+            ron::Serializer::new(&mut text, Some(ron::ser::PrettyConfig::default())).unwrap();
+        let mut file = (&mut serializer)
+            .serialize_struct("SceneFile", 2 + kinds.len())
+            .unwrap();
+        file.serialize_field("groups", &self.groups).unwrap();
+        for (kind, primitives) in kinds.iter().zip(&self.primitives) {
+            kind.write_list(&mut file, primitives).unwrap();
+        }
+        file.serialize_field("lights", &self.lights).unwrap();
+        file.end().unwrap();
+        drop(serializer);
+        String::from_utf8(text).unwrap()
+    }
+
+    /// Everything the text leaves out is empty
+    pub(crate) fn from_text(text: &str, kinds: &[PrimitiveKind]) -> anyhow::Result<Self> {
+        let mut deserializer = ron::Deserializer::from_str(text)?;
+        Ok((&mut deserializer).deserialize_struct("SceneFile", &[], SceneFileVisitor { kinds })?)
+    }
+
+    pub(crate) fn load(path: &Path, kinds: &[PrimitiveKind]) -> anyhow::Result<Self> {
+        Self::from_text(&std::fs::read_to_string(path)?, kinds)
+            .map_err(|error| error.context(format!("in {}", path.display())))
+    }
+
+    pub(crate) fn save(&self, path: &Path, kinds: &[PrimitiveKind]) -> anyhow::Result<()> {
+        Ok(std::fs::write(path, self.to_text(kinds))?)
+    }
+
+    /// The number of groups and primitives in it
+    pub(crate) fn node_count(&self) -> usize {
+        self.groups.len() + self.primitives.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+impl Group {
+    pub(crate) fn local_transform(&self) -> Transform {
+        Transform {
+            position: self.position,
+            rotation: self.rotation,
+        }
+    }
+}
+
+/// Anything that can be selected or moved between groups
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum NodeId {
+    Group(usize),
+    /// The index of its kind among the registered kinds, and its id
+    Primitive(usize, usize),
+}
+
+/// Where the inside of `group` is in world space, the identity for the top level
+pub(crate) fn group_transform(groups: &[Group], mut group: Option<usize>) -> Transform {
+    let mut transform = Transform::identity();
+    // bounded, so a broken scene with a cycle in it cannot hang
+    for _ in 0..=groups.len() {
+        let Some(parent) = group.and_then(|id| groups.iter().find(|group| group.id == id)) else {
+            break;
+        };
+        transform = parent.local_transform().then(transform);
+        group = parent.parent;
+    }
+    transform
+}
+
+/// Whether `predicate` holds for `group` or any group it is inside of
+pub(crate) fn any_group(
+    groups: &[Group],
+    mut group: Option<usize>,
+    predicate: impl Fn(&Group) -> bool,
+) -> bool {
+    for _ in 0..=groups.len() {
+        let Some(current) = group.and_then(|id| groups.iter().find(|group| group.id == id)) else {
+            return false;
+        };
+        if predicate(current) {
+            return true;
+        }
+        group = current.parent;
+    }
+    false
+}
+
+/// Whether `group` is `ancestor` or somewhere inside of it
+pub(crate) fn is_inside(groups: &[Group], group: Option<usize>, ancestor: usize) -> bool {
+    any_group(groups, group, |group| group.id == ancestor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::hyper_sphere::HyperSphere;
+    use crate::BUILT_IN_PRIMITIVE_KINDS;
+
+    #[test]
+    fn scene_files_fill_in_what_they_leave_out() {
+        let kinds = &BUILT_IN_PRIMITIVE_KINDS;
+        let file = SceneFile::from_text(
+            "(hyper_spheres: [(
+                name: \"Sphere\",
+                id: 3,
+                position: (x: 1.0, y: 2.0, z: 3.0, w: 4.0),
+                color: (x: 1.0, y: 0.5, z: 0.0),
+                radius: 2.0,
+                emissive_color: (x: 0.0, y: 0.0, z: 0.0),
+                emission_strength: 0.0,
+            )])",
+            kinds,
+        )
+        .unwrap();
+        assert!(file.groups.is_empty() && file.lights.is_empty());
+        let hyper_sphere = file.primitives[0][0]
+            .as_any()
+            .downcast_ref::<HyperSphere>()
+            .unwrap();
+        assert_eq!(hyper_sphere.radius, 2.0);
+        let node = &hyper_sphere.node;
+        assert_eq!((node.id, node.parent), (3, None));
+        assert_eq!(node.rotation, identity_rotation());
+        assert!(!node.hidden && !node.locked);
+
+        let text = file.to_text(kinds);
+        assert!(text.contains("hyper_spheres:"));
+        let round_trip = SceneFile::from_text(&text, kinds).unwrap();
+        assert!(round_trip.primitives == file.primitives);
+    }
+
+    #[test]
+    fn scene_files_with_unknown_primitives_do_not_load() {
+        assert!(SceneFile::from_text("(cubes: [])", &BUILT_IN_PRIMITIVE_KINDS).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! Puts shaders together from the modules in `shaders`, leaving out the features that are not used
+
+use crate::primitive::{self, PrimitiveKind};
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// Every module a shader can `#import` by name, besides the ones for the kinds of primitives and
+/// `primitives`, which has all of them
+pub(crate) const MODULES: [(&str, &str); 11] = [
+    ("blue_noise", include_str!("./shaders/blue_noise.wgsl")),
+    ("camera", include_str!("./shaders/camera.wgsl")),
+    ("common", include_str!("./shaders/common.wgsl")),
+    ("integrator", include_str!("./shaders/integrator.wgsl")),
+    ("lights", include_str!("./shaders/lights.wgsl")),
+    ("material", include_str!("./shaders/material.wgsl")),
+    ("preview", include_str!("./shaders/preview.wgsl")),
+    ("r2", include_str!("./shaders/r2.wgsl")),
+    ("sampling", include_str!("./shaders/sampling.wgsl")),
+    ("scene", include_str!("./shaders/scene.wgsl")),
+    ("sobol", include_str!("./shaders/sobol.wgsl")),
+];
+
+pub(crate) fn built_in_module(
+    kinds: &[PrimitiveKind],
+    name: &str,
+) -> Result<Cow<'static, str>, String> {
+    if name == "primitives" {
+        return Ok(primitive::wgsl_module(kinds).into());
+    }
+    MODULES
+        .iter()
+        .copied()
+        .chain(kinds.iter().map(|kind| (kind.name, kind.wgsl)))
+        .find(|(module, _)| *module == name)
+        .map(|(_, source)| Cow::Borrowed(source))
+        .ok_or_else(|| format!("there is no module called {name}"))
+}
+
+/// What naga calls the source in the locations of its errors, before `Composed::map_locations`
+pub(crate) const COMPOSED_PATH: &str = "composed";
+
+/// A shader put together from its modules, which remembers where every line of it came from
+pub(crate) struct Composed {
+    pub(crate) source: String,
+    /// The module and line in it for every line of `source`
+    origins: Vec<(String, usize)>,
+}
+
+impl Composed {
+    /// Points the `composed:line:column` locations in an error naga emitted with `COMPOSED_PATH` to the
+    /// modules the lines came from
+    pub(crate) fn map_locations(&self, message: &str) -> String {
+        let prefix = format!("{COMPOSED_PATH}:");
+        let mut mapped = String::new();
+        let mut rest = message;
+        while let Some(start) = rest.find(&prefix) {
+            mapped.push_str(&rest[..start]);
+            rest = &rest[start + prefix.len()..];
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let origin = rest[..digits]
+                .parse::<usize>()
+                .ok()
+                .and_then(|line| self.origins.get(line.checked_sub(1)?));
+            match origin {
+                Some((module, line)) => {
+                    mapped += &format!("{module}:{line}");
+                    rest = &rest[digits..];
+                }
+                None => mapped.push_str(&prefix),
+            }
+        }
+        mapped.push_str(rest);
+        mapped
+    }
+}
+
+/// Pastes the module `name` in place of `#import name`, only the first time it is imported.
+/// The lines between `#ifdef NAME` or `#ifndef NAME` and `#else` or `#endif` are only kept when
+/// `NAME` is or is not in `defines`
+pub(crate) fn compose(
+    name: &str,
+    source: &str,
+    load_module: &dyn Fn(&str) -> Result<Cow<'static, str>, String>,
+    defines: &[&str],
+) -> Result<Composed, String> {
+    let mut composed = Composed {
+        source: String::new(),
+        origins: vec![],
+    };
+    let mut imported = HashSet::new();
+    compose_into(
+        &mut composed,
+        name,
+        source,
+        load_module,
+        defines,
+        &mut imported,
+    )?;
+    Ok(composed)
+}
+
+fn compose_into(
+    composed: &mut Composed,
+    name: &str,
+    source: &str,
+    load_module: &dyn Fn(&str) -> Result<Cow<'static, str>, String>,
+    defines: &[&str],
+    imported: &mut HashSet<String>,
+) -> Result<(), String> {
+    // whether the lines are kept, for every `#ifdef` the current line is inside of
+    let mut conditions = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let location = format!("{name}:{}", index + 1);
+        let directive = line.trim();
+        let keep = conditions.iter().all(|&condition| condition);
+        if let Some(define) = directive.strip_prefix("#ifdef ") {
+            conditions.push(defines.contains(&define.trim()));
+        } else if let Some(define) = directive.strip_prefix("#ifndef ") {
+            conditions.push(!defines.contains(&define.trim()));
+        } else if directive == "#else" {
+            let condition = conditions
+                .last_mut()
+                .ok_or_else(|| format!("{location}: #else without #ifdef"))?;
+            *condition = !*condition;
+        } else if directive == "#endif" {
+            conditions
+                .pop()
+                .ok_or_else(|| format!("{location}: #endif without #ifdef"))?;
+        } else if !keep {
+        } else if let Some(module) = directive.strip_prefix("#import ") {
+            let module = module.trim();
+            // marked before it is pasted in, so modules can import each other
+            if imported.insert(module.to_string()) {
+                let module_source =
+                    load_module(module).map_err(|error| format!("{location}: {error}"))?;
+                compose_into(
+                    composed,
+                    module,
+                    &module_source,
+                    load_module,
+                    defines,
+                    imported,
+                )?;
+            }
+        } else if directive.starts_with('#') {
+            return Err(format!("{location}: unknown directive {directive}"));
+        } else {
+            composed.source.push_str(line);
+            composed.source.push('\n');
+            composed.origins.push((name.to_string(), index + 1));
+        }
+    }
+    if !conditions.is_empty() {
+        return Err(format!("{name}: #ifdef without #endif"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::wgpu::naga;
+
+    /// Every name `raytracing.wgsl` checks with `#ifdef`
+    const DEFINES: [&str; 5] = [
+        "SAMPLER_SOBOL",
+        "SAMPLER_R2",
+        "SAMPLER_BLUE_NOISE",
+        "PREVIEW",
+        "ADAPTIVE_SAMPLING",
+    ];
+
+    #[test]
+    fn every_feature_combination_validates() {
+        // without any kind of primitive that glows, the light functions only have their defaults
+        for kinds in [&crate::BUILT_IN_PRIMITIVE_KINDS[..], &[]] {
+            for combination in 0..1 << DEFINES.len() {
+                let defines = DEFINES
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| combination & (1 << index) != 0)
+                    .map(|(_, define)| *define)
+                    .collect::<Vec<_>>();
+                validate(
+                    "raytracing.wgsl",
+                    include_str!("./raytracing.wgsl"),
+                    kinds,
+                    &defines,
+                );
+            }
+        }
+        // the other shaders have no features to combine
+        validate(
+            "statistics.wgsl",
+            include_str!("./statistics.wgsl"),
+            &crate::BUILT_IN_PRIMITIVE_KINDS,
+            &[],
+        );
+    }
+
+    /// Composes `source` and panics with where it went wrong when naga does not accept it
+    fn validate(name: &str, source: &str, kinds: &[PrimitiveKind], defines: &[&str]) {
+        let source = compose(name, source, &|name| built_in_module(kinds, name), defines)
+            .unwrap()
+            .source;
+        let module = naga::front::wgsl::parse_str(&source).unwrap_or_else(|error| {
+            panic!("{name} with {defines:?}: {}", error.emit_to_string(&source))
+        });
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .unwrap_or_else(|error| {
+            panic!("{name} with {defines:?}: {}", error.emit_to_string(&source))
+        });
+    }
+
+    #[test]
+    fn every_module_imports_what_it_uses() {
+        let kinds = &crate::BUILT_IN_PRIMITIVE_KINDS;
+        let names = MODULES
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(kinds.iter().map(|kind| kind.name))
+            .chain(["primitives"]);
+        for name in names {
+            for defines in [&[][..], &DEFINES] {
+                validate(name, &format!("#import {name}"), kinds, defines);
+            }
+        }
+    }
+
+    #[test]
+    fn conditions_nest_and_modules_are_imported_once() {
+        let load_module = |name: &str| -> Result<Cow<'static, str>, String> {
+            match name {
+                "a" => Ok("#import b\na".into()),
+                "b" => Ok("#import a\nb".into()),
+                _ => Err(format!("there is no module called {name}")),
+            }
+        };
+        let source = "#import a\n#ifdef X\n#ifndef Y\nx\n#else\ny\n#endif\n#endif\n#import b";
+        assert_eq!(
+            compose("main", source, &load_module, &["X"])
+                .unwrap()
+                .source,
+            "b\na\nx\n"
+        );
+        assert_eq!(
+            compose("main", source, &load_module, &["X", "Y"])
+                .unwrap()
+                .source,
+            "b\na\ny\n"
+        );
+        assert!(compose("main", "#ifdef X", &load_module, &[]).is_err());
+        assert!(compose("main", "#import c", &load_module, &[]).is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_module_the_line_came_from() {
+        let load_module = |_: &str| -> Result<Cow<'static, str>, String> {
+            Ok("fn a() {}\n\nfn b() -> u32 {\n    return c;\n}".into())
+        };
+        let composed = compose("main", "#import a\nfn main() {}", &load_module, &[]).unwrap();
+        let error = naga::front::wgsl::parse_str(&composed.source)
+            .map(|_| ())
+            .unwrap_err()
+            .emit_to_string_with_path(&composed.source, COMPOSED_PATH);
+        let mapped = composed.map_locations(&error);
+        assert!(mapped.contains("a:4:12"), "{mapped}");
+        assert!(!mapped.contains(COMPOSED_PATH), "{mapped}");
+    }
+}
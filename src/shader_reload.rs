@@ -0,0 +1,198 @@
+//! Loading the shaders from disk while the app runs, for working on them without rebuilding
+
+use crate::file_watch::FileWatcher;
+use crate::shader_composer;
+use crate::shader_composer::{Composed, COMPOSED_PATH};
+use crate::PrimitiveKind;
+use eframe::wgpu::{self, naga};
+use std::borrow::Cow;
+use std::future::Future;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Shader {
+    Raytracing,
+    TextureCopy,
+    Statistics,
+}
+
+impl Shader {
+    pub(crate) const ALL: [Shader; 3] =
+        [Shader::Raytracing, Shader::TextureCopy, Shader::Statistics];
+
+    pub(crate) fn file_name(self) -> &'static str {
+        match self {
+            Shader::Raytracing => "raytracing.wgsl",
+            Shader::TextureCopy => "texture_copy.wgsl",
+            Shader::Statistics => "statistics.wgsl",
+        }
+    }
+
+    fn pipeline_label(self) -> &'static str {
+        match self {
+            Shader::Raytracing => "Raytracing Pipeline",
+            Shader::TextureCopy => "Texture Copy Pipeline",
+            Shader::Statistics => "Statistics Pipeline",
+        }
+    }
+
+    /// The source that is built into the app, before its modules are put in
+    fn built_in_source(self) -> &'static str {
+        match self {
+            Shader::Raytracing => include_str!("./raytracing.wgsl"),
+            Shader::TextureCopy => include_str!("./texture_copy.wgsl"),
+            Shader::Statistics => include_str!("./statistics.wgsl"),
+        }
+    }
+}
+
+/// The errors since the matching `push_error_scope`, which native backends report right away
+fn pop_error_scope(device: &wgpu::Device) -> Option<wgpu::Error> {
+    let mut future = std::pin::pin!(device.pop_error_scope());
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    let poll = future.as_mut().poll(&mut context);
+    match poll {
+        std::task::Poll::Ready(error) => error,
+        std::task::Poll::Pending => None,
+    }
+}
+
+/// Compiles the `main` entry point of `composed`, or explains why it does not compile
+pub(crate) fn create_pipeline(
+    device: &wgpu::Device,
+    shader: Shader,
+    composed: &Composed,
+    layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::ComputePipeline, String> {
+    // naga says where in the source the problem is, which the errors from wgpu leave out
+    let source = composed.source.as_str();
+    let module = naga::front::wgsl::parse_str(source).map_err(|error| {
+        composed.map_locations(&error.emit_to_string_with_path(source, COMPOSED_PATH))
+    })?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| {
+        composed.map_locations(&error.emit_to_string_with_path(source, COMPOSED_PATH))
+    })?;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(shader.file_name()),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(shader.pipeline_label()),
+        layout: Some(layout),
+        module: &module,
+        entry_point: "main",
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+    });
+    let error = pop_error_scope(device);
+    match error {
+        Some(error) => Err(error.to_string()),
+        None => Ok(pipeline),
+    }
+}
+
+/// Whether the shaders get loaded from disk, and which of them did not compile
+pub(crate) struct ShaderReload {
+    /// Where the shaders get loaded from, the source directory by default
+    pub(crate) directory: String,
+    /// Set while the shaders in `directory` are used instead of the built in ones, for every shader and
+    /// module in it
+    watchers: Option<Vec<FileWatcher>>,
+    /// Why the shaders that failed to compile last time did, the pipelines from before are kept for those
+    pub(crate) errors: Vec<(Shader, String)>,
+    /// Whose modules the shaders can import
+    primitive_kinds: Vec<PrimitiveKind>,
+}
+
+impl ShaderReload {
+    pub(crate) fn new(primitive_kinds: Vec<PrimitiveKind>) -> Self {
+        Self {
+            directory: concat!(env!("CARGO_MANIFEST_DIR"), "/src").into(),
+            watchers: None,
+            errors: vec![],
+            primitive_kinds,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.watchers.is_some()
+    }
+
+    fn path(&self, shader: Shader) -> PathBuf {
+        PathBuf::from(&self.directory).join(shader.file_name())
+    }
+
+    fn module_path(&self, name: &str) -> PathBuf {
+        PathBuf::from(&self.directory)
+            .join("shaders")
+            .join(format!("{name}.wgsl"))
+    }
+
+    /// Switches between the shaders on disk and the built in ones, all of which need compiling again
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.watchers = enabled.then(|| {
+            Shader::ALL
+                .into_iter()
+                .map(|shader| self.path(shader))
+                .chain(
+                    shader_composer::MODULES
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .chain(self.primitive_kinds.iter().map(|kind| kind.name))
+                        .map(|name| self.module_path(name)),
+                )
+                .map(FileWatcher::new)
+                .collect()
+        });
+    }
+
+    /// Whether any shader or module was modified on disk since this was last called. The shaders
+    /// share modules, so they all get compiled again
+    pub(crate) fn changed(&mut self) -> bool {
+        // every watcher gets to look, so one change does not get reported twice
+        self.watchers
+            .iter_mut()
+            .flatten()
+            .map(FileWatcher::changed)
+            .filter(|&changed| changed)
+            .count()
+            > 0
+    }
+
+    /// The source of the shader with its modules put in, for the features in `defines`
+    pub(crate) fn source(&self, shader: Shader, defines: &[&str]) -> Result<Composed, String> {
+        let read = |path: PathBuf| {
+            std::fs::read_to_string(&path)
+                .map(Cow::Owned)
+                .map_err(|error| format!("{}: {error}", path.display()))
+        };
+        if !self.is_enabled() {
+            return shader_composer::compose(
+                shader.file_name(),
+                shader.built_in_source(),
+                &|name| shader_composer::built_in_module(&self.primitive_kinds, name),
+                defines,
+            );
+        }
+        // `primitives` gets generated, and primitives from elsewhere may not have a file in here
+        shader_composer::compose(
+            shader.file_name(),
+            &read(self.path(shader))?,
+            &|name| {
+                let path = self.module_path(name);
+                if path.exists() {
+                    read(path)
+                } else {
+                    shader_composer::built_in_module(&self.primitive_kinds, name)
+                }
+            },
+            defines,
+        )
+    }
+}
@@ -0,0 +1,17 @@
+#import camera
+#import sampling
+
+@group(1)
+@binding(1)
+var blue_noise: texture_2d<f32>;
+
+// a spatial blue noise tile shifted by a different amount for every dimension and seed,
+// and animated over samples with the golden ratio. The seed is the same for every pixel,
+// since shifting the pixels apart would break up the blue noise between them
+fn blue_noise_value(state: ptr<function, RandomState>, dimension: u32) -> f32 {
+    let size = textureDimensions(blue_noise);
+    let seed = hash(camera.seed);
+    let shift = vec2<u32>(hash(dimension ^ seed), hash(dimension ^ seed ^ 0x9e3779b9u));
+    let value = textureLoad(blue_noise, ((*state).pixel + shift) % size, 0).r;
+    return fract(value + f32((*state).sample_index) * 0.61803398875);
+}
@@ -0,0 +1,35 @@
+#import common
+
+struct Camera {
+    position: vec4<f32>,
+    tan_half_fov: f32,
+    up_sky_color: vec3<f32>,
+    down_sky_color: vec3<f32>,
+    bounce_count: u32,
+    russian_roulette_depth: u32,
+    sample_count: u32,
+    sampler_kind: u32,
+    seed: u32,
+    seed_offset: u32,
+    frame_count: u32,
+    preview_mode: u32,
+    ambient_occlusion_distance: f32,
+    // zero when adaptive sampling is disabled
+    adaptive_sampling_threshold: f32,
+    adaptive_sampling_min_sample_count: u32,
+    // the textures can hold just one tile of the whole image
+    image_size: vec2<u32>,
+    tile_offset: vec2<u32>,
+}
+
+@group(1)
+@binding(0)
+var<uniform> camera: Camera;
+
+const preview_mode_off: u32 = 0u;
+const preview_mode_direct: u32 = 1u;
+const preview_mode_ambient_occlusion: u32 = 2u;
+
+fn sky_color(ray: Ray) -> vec3<f32> {
+    return mix(camera.down_sky_color, camera.up_sky_color, ray.direction.y * 0.5 + 0.5);
+}
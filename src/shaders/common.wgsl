@@ -0,0 +1,26 @@
+struct Ray {
+    origin: vec4<f32>,
+    direction: vec4<f32>,
+}
+
+struct Hit {
+    hit: bool,
+    // counts through every kind of primitive, in the order they are registered
+    index: u32,
+    primitive_kind: u32,
+    // the index in the buffer of the kind of primitive
+    primitive_index: u32,
+    color: vec3<f32>,
+    emission: vec3<f32>,
+    distance: f32,
+    position: vec4<f32>,
+    normal: vec4<f32>,
+}
+
+var<private> bounce_count: u32;
+var<private> intersection_test_count: u32;
+
+const min_distance: f32 = 0.001;
+const max_distance: f32 = 3.40282347e+38;
+
+const pi: f32 = 3.14159265;
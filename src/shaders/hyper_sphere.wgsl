@@ -0,0 +1,63 @@
+#import common
+#import sampling
+
+struct HyperSphere {
+    position: vec4<f32>,
+    color: vec3<f32>,
+    radius: f32,
+    emissive_color: vec3<f32>,
+    emission_strength: f32,
+}
+
+fn intersect_hyper_sphere(ray: Ray, hyper_sphere: HyperSphere) -> Hit {
+    var hit: Hit;
+    hit.hit = false;
+
+    let oc = ray.origin - hyper_sphere.position;
+    let a = dot(ray.direction, ray.direction);
+    let half_b = dot(oc, ray.direction);
+    let c = dot(oc, oc) - hyper_sphere.radius * hyper_sphere.radius;
+    let discriminant = half_b * half_b - a * c;
+
+    if discriminant < 0.0 {
+        return hit;
+    }
+
+    let sqrt_discriminant = sqrt(discriminant);
+    let t0 = (-half_b - sqrt_discriminant) / a;
+    let t1 = (-half_b + sqrt_discriminant) / a;
+
+    hit.distance = t0;
+    if hit.distance < min_distance {
+        return hit;
+    }
+
+    hit.hit = true;
+    hit.color = hyper_sphere.color;
+    hit.emission = hyper_sphere_emission(hyper_sphere);
+    hit.position = ray.origin + ray.direction * hit.distance;
+    hit.normal = (hit.position - hyper_sphere.position) / hyper_sphere.radius;
+    return hit;
+}
+
+fn hyper_sphere_emission(hyper_sphere: HyperSphere) -> vec3<f32> {
+    return hyper_sphere.emissive_color * hyper_sphere.emission_strength;
+}
+
+// solid angle pdf of `sample_hyper_sphere_light` picking `point` as seen from `origin`
+fn hyper_sphere_light_pdf(hyper_sphere: HyperSphere, origin: vec4<f32>, point: vec4<f32>) -> f32 {
+    let to_point = point - origin;
+    let distance = length(to_point);
+    let cos_light = dot((point - hyper_sphere.position) / hyper_sphere.radius, -to_point / distance);
+    if cos_light <= 0.0 {
+        return 0.0;
+    }
+    let area = pi * pi * hyper_sphere.radius * hyper_sphere.radius * hyper_sphere.radius;
+    return (distance * distance * distance) / (area * cos_light);
+}
+
+// picks a point uniformly on the half of the hyper sphere facing `origin`
+fn sample_hyper_sphere_light(hyper_sphere: HyperSphere, origin: vec4<f32>, state: ptr<function, RandomState>) -> vec4<f32> {
+    let normal = random_direction_in_hemisphere(state, normalize(origin - hyper_sphere.position));
+    return hyper_sphere.position + normal * hyper_sphere.radius;
+}
@@ -0,0 +1,57 @@
+#import camera
+#import lights
+#import material
+#import primitives
+#import scene
+
+fn trace(ray_: Ray, state: ptr<function, RandomState>, first_hit: ptr<function, Hit>) -> vec3<f32> {
+    var ray = ray_;
+    var incoming_light = vec3<f32>(0.0);
+    var ray_color = vec3<f32>(1.0);
+    var brdf_pdf = 0.0;
+
+    for (var i = 0u; i < camera.bounce_count; i += 1u) {
+        bounce_count += 1u;
+        let hit = get_closest_hit(ray);
+        if i == 0u {
+            *first_hit = hit;
+        }
+        if hit.hit {
+            if any(hit.emission != vec3<f32>(0.0)) {
+                // camera rays can only find lights by hitting them, after that `sample_lights` also had a chance
+                var weight = 1.0;
+                // the pdf is zero for the kinds that do not get sampled there, which only have this chance
+                if i > 0u {
+                    let light_pdf = light_primitive_pdf(hit.primitive_kind, hit.primitive_index, ray.origin, hit.position);
+                    weight = power_heuristic(brdf_pdf, light_pdf);
+                }
+                incoming_light += hit.emission * ray_color * weight;
+            }
+
+            let brdf = lambert_brdf(hit.color);
+            incoming_light += sample_lights(hit.position, hit.normal, brdf, state) * ray_color;
+
+            ray.origin = hit.position;
+            ray.direction = random_direction_cosine_weighted(state, hit.normal);
+            brdf_pdf = cosine_weighted_pdf(dot(hit.normal, ray.direction));
+
+            // brdf * cos_theta / brdf_pdf, the cosine and the 4D normalisation cancel out
+            ray_color *= hit.color;
+
+            // after enough bounces, randomly end paths that carry little light and
+            // boost the survivors by the same factor so the result stays unbiased
+            if i + 1u >= camera.russian_roulette_depth {
+                let survival_probability = min(max(ray_color.r, max(ray_color.g, ray_color.b)), 1.0);
+                if random_value(state) >= survival_probability {
+                    break;
+                }
+                ray_color /= survival_probability;
+            }
+        } else {
+            incoming_light += sky_color(ray) * ray_color;
+            break;
+        }
+    }
+
+    return incoming_light;
+}
@@ -0,0 +1,88 @@
+#import common
+#import material
+#import primitives
+#import scene
+
+const light_kind_point: u32 = 0u;
+const light_kind_directional: u32 = 1u;
+
+struct Light {
+    kind: u32,
+    position: vec4<f32>,
+    direction: vec4<f32>,
+    color: vec3<f32>,
+    intensity: f32,
+}
+
+struct Lights {
+    count: u32,
+    data: array<Light>,
+}
+
+@group(2)
+@binding(0)
+var<storage, read> lights: Lights;
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b <= 0.0 {
+        return 0.0;
+    }
+    return a / (a + b);
+}
+
+// next event estimation: light arriving at `position` directly from every light source
+fn sample_lights(position: vec4<f32>, normal: vec4<f32>, brdf: vec3<f32>, state: ptr<function, RandomState>) -> vec3<f32> {
+    var incoming_light = vec3<f32>(0.0);
+
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = lights.data[i];
+
+        var direction: vec4<f32>;
+        var distance: f32;
+        var falloff: f32;
+        if light.kind == light_kind_point {
+            let to_light = light.position - position;
+            distance = length(to_light);
+            direction = to_light / distance;
+            // the surface area of a 3-sphere grows with the cube of its radius
+            falloff = 1.0 / (distance * distance * distance);
+        } else {
+            direction = -normalize(light.direction);
+            distance = max_distance;
+            falloff = 1.0;
+        }
+
+        let cos_theta = dot(normal, direction);
+        if cos_theta <= 0.0 || is_occluded(position, direction, distance) {
+            continue;
+        }
+        incoming_light += brdf * light.color * light.intensity * falloff * cos_theta;
+    }
+
+    for (var kind = 0u; kind < primitive_kind_count; kind += 1u) {
+        for (var i = 0u; i < light_primitive_count(kind); i += 1u) {
+            let emission = light_primitive_emission(kind, i);
+            if all(emission == vec3<f32>(0.0)) {
+                continue;
+            }
+
+            let point = sample_light_primitive(kind, i, position, state);
+            let to_light = point - position;
+            let distance = length(to_light);
+            let direction = to_light / distance;
+
+            let cos_theta = dot(normal, direction);
+            let light_pdf = light_primitive_pdf(kind, i, position, point);
+            // points facing away from `position` are hidden behind the rest of the primitive
+            if cos_theta <= 0.0 || light_pdf <= 0.0 || is_occluded(position, direction, distance * 0.999) {
+                continue;
+            }
+            let weight = power_heuristic(light_pdf, cosine_weighted_pdf(cos_theta));
+            incoming_light += brdf * emission * cos_theta * weight / light_pdf;
+        }
+    }
+
+    return incoming_light;
+}
@@ -0,0 +1,16 @@
+#import common
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// the cosine-weighted integral over a hemisphere of the 3-sphere is 4pi/3,
+// so this is the 4D equivalent of the usual color / pi
+fn lambert_brdf(color: vec3<f32>) -> vec3<f32> {
+    return color * (3.0 / (4.0 * pi));
+}
+
+// solid angle pdf of `random_direction_cosine_weighted`
+fn cosine_weighted_pdf(cos_theta: f32) -> f32 {
+    return max(cos_theta, 0.0) * (3.0 / (4.0 * pi));
+}
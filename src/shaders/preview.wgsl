@@ -0,0 +1,28 @@
+#import camera
+#import lights
+#import material
+#import scene
+
+// cheap shading used while the scene is being edited, only direct light and a bit of sky
+fn preview(ray: Ray, state: ptr<function, RandomState>, first_hit: ptr<function, Hit>) -> vec3<f32> {
+    bounce_count += 1u;
+    let hit = get_closest_hit(ray);
+    *first_hit = hit;
+    if !hit.hit {
+        return sky_color(ray);
+    }
+
+    // light coming from the camera keeps the shape readable even without any lights
+    var ambient = 0.5 + 0.5 * max(dot(hit.normal, -ray.direction), 0.0);
+    if camera.preview_mode == preview_mode_ambient_occlusion {
+        let direction = random_direction_cosine_weighted(state, hit.normal);
+        if is_occluded(hit.position, direction, camera.ambient_occlusion_distance) {
+            ambient = 0.0;
+        }
+    }
+
+    var sky_ray: Ray;
+    sky_ray.direction = hit.normal;
+    let brdf = lambert_brdf(hit.color);
+    return hit.emission + hit.color * sky_color(sky_ray) * ambient + sample_lights(hit.position, hit.normal, brdf, state);
+}
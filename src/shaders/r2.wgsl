@@ -0,0 +1,11 @@
+#import sampling
+
+// the plastic number based R2 sequence, with a random rotation for every pixel and pair of dimensions
+fn r2(state: ptr<function, RandomState>, dimension: u32) -> f32 {
+    var alpha = 3242174889u;
+    if dimension % 2u == 1u {
+        alpha = 2447445413u;
+    }
+    let offset = hash((*state).pixel_seed ^ hash(dimension));
+    return to_unit_float((*state).sample_index * alpha + offset);
+}
@@ -0,0 +1,101 @@
+#import camera
+#ifdef SAMPLER_SOBOL
+#import sobol
+#endif
+#ifdef SAMPLER_R2
+#import r2
+#endif
+#ifdef SAMPLER_BLUE_NOISE
+#import blue_noise
+#endif
+
+const sampler_random: u32 = 0u;
+const sampler_sobol: u32 = 1u;
+const sampler_r2: u32 = 2u;
+const sampler_blue_noise: u32 = 3u;
+
+// every call to `random_value` consumes the next dimension of the current sample,
+// so low discrepancy samplers can stratify each decision along the path separately
+struct RandomState {
+    pixel: vec2<u32>,
+    pixel_seed: u32,
+    sample_index: u32,
+    dimension: u32,
+    pcg_state: u32,
+}
+
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let result = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (result >> 22u) ^ result;
+}
+
+fn to_unit_float(value: u32) -> f32 {
+    return f32(value >> 8u) / 16777216.0;
+}
+
+fn random_value(state: ptr<function, RandomState>) -> f32 {
+    let dimension = (*state).dimension;
+    (*state).dimension += 1u;
+
+    switch camera.sampler_kind {
+#ifdef SAMPLER_SOBOL
+        case sampler_sobol: {
+            return sobol_owen(state, dimension);
+        }
+#endif
+#ifdef SAMPLER_R2
+        case sampler_r2: {
+            return r2(state, dimension);
+        }
+#endif
+#ifdef SAMPLER_BLUE_NOISE
+        case sampler_blue_noise: {
+            return blue_noise_value(state, dimension);
+        }
+#endif
+        default: {
+            (*state).pcg_state = hash((*state).pcg_state);
+            return f32((*state).pcg_state) / 4294967295.0;
+        }
+    }
+}
+
+fn random_value_normal_distribution(state: ptr<function, RandomState>) -> f32 {
+    let theta = 2.0 * 3.1415926 * random_value(state);
+    // low discrepancy samplers can return exactly zero
+    let rho = sqrt(-2.0 * log(max(random_value(state), 1.0e-12)));
+    return rho * cos(theta);
+}
+
+fn random_direction(state: ptr<function, RandomState>) -> vec4<f32> {
+    return normalize(vec4<f32>(
+        random_value_normal_distribution(state),
+        random_value_normal_distribution(state),
+        random_value_normal_distribution(state),
+        random_value_normal_distribution(state),
+    ));
+}
+
+fn random_direction_in_hemisphere(state: ptr<function, RandomState>, normal: vec4<f32>) -> vec4<f32> {
+    var direction = random_direction(state);
+    if dot(direction, normal) < 0.0 {
+        direction *= -1.0;
+    }
+    return direction;
+}
+
+// cosine-weighted direction around `normal`, by lifting a uniform point in the 3-ball
+// tangent to the hemisphere up onto it (Malley's method works in any dimension)
+fn random_direction_cosine_weighted(state: ptr<function, RandomState>, normal: vec4<f32>) -> vec4<f32> {
+    // a normally distributed vector projected onto the tangent space is still isotropic there
+    let gaussian = vec4<f32>(
+        random_value_normal_distribution(state),
+        random_value_normal_distribution(state),
+        random_value_normal_distribution(state),
+        random_value_normal_distribution(state),
+    );
+    let tangent = normalize(gaussian - dot(gaussian, normal) * normal);
+    let radius = pow(random_value(state), 1.0 / 3.0);
+    return tangent * radius + normal * sqrt(max(1.0 - radius * radius, 0.0));
+}
@@ -0,0 +1,14 @@
+#import common
+#import primitives
+
+fn get_closest_hit(ray: Ray) -> Hit {
+    return intersect_primitives(ray);
+}
+
+fn is_occluded(origin: vec4<f32>, direction: vec4<f32>, distance: f32) -> bool {
+    var ray: Ray;
+    ray.origin = origin;
+    ray.direction = direction;
+    let hit = get_closest_hit(ray);
+    return hit.hit && hit.distance < distance;
+}
@@ -0,0 +1,72 @@
+#import sampling
+
+// the first 4 dimensions of the sobol sequence, using the primitive polynomials
+// and initial direction numbers from Joe and Kuo
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0u {
+        return reverseBits(index);
+    }
+
+    var result = 0u;
+    var v1 = 0u;
+    var v2 = 0u;
+    var v3 = 0u;
+    for (var bit = 0u; bit < 32u; bit += 1u) {
+        var v: u32;
+        switch dimension {
+            case 1u: {
+                if bit == 0u {
+                    v = 1u << 31u;
+                } else {
+                    v = v1 ^ (v1 >> 1u);
+                }
+            }
+            case 2u: {
+                if bit == 0u {
+                    v = 1u << 31u;
+                } else if bit == 1u {
+                    v = 3u << 30u;
+                } else {
+                    v = v2 ^ (v2 >> 2u) ^ v1;
+                }
+            }
+            default: {
+                if bit == 0u {
+                    v = 1u << 31u;
+                } else if bit == 1u {
+                    v = 3u << 30u;
+                } else if bit == 2u {
+                    v = 1u << 29u;
+                } else {
+                    v = v3 ^ (v3 >> 3u) ^ v2;
+                }
+            }
+        }
+        if ((index >> bit) & 1u) != 0u {
+            result ^= v;
+        }
+        v3 = v2;
+        v2 = v1;
+        v1 = v;
+    }
+    return result;
+}
+
+// hash based owen scrambling from "Practical Hash-based Owen Scrambling" (Burley 2020)
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    var x = reverseBits(value);
+    x += seed;
+    x ^= x * 0x6c50b47cu;
+    x ^= x * 0xb82f1e52u;
+    x ^= x * 0xc7afe638u;
+    x ^= x * 0x8d22f6e6u;
+    return reverseBits(x);
+}
+
+// higher dimensions are padded with shuffled copies of the first 4
+fn sobol_owen(state: ptr<function, RandomState>, dimension: u32) -> f32 {
+    let group_seed = hash((*state).pixel_seed ^ hash(dimension / 4u));
+    let index = nested_uniform_scramble((*state).sample_index, group_seed);
+    let value = sobol(index, dimension % 4u);
+    return to_unit_float(nested_uniform_scramble(value, hash(group_seed ^ dimension)));
+}
@@ -0,0 +1,230 @@
+use crate::readback::Readback;
+use crate::shader_reload::{self, Shader, ShaderReload};
+use eframe::wgpu;
+use encase::{ShaderSize, ShaderType, UniformBuffer};
+
+const WORKGROUP_SIZE: (u32, u32) = (16, 16);
+
+#[derive(ShaderType)]
+struct GpuStatistics {
+    sample_count: u32,
+}
+
+/// How far along the image is, from the per pixel moments the raytracing shader keeps
+#[derive(Clone, Copy)]
+pub(crate) struct NoiseEstimate {
+    /// The mean over all pixels of the standard error of the pixel relative to its luminance
+    pub(crate) relative_error: f32,
+    /// The mean over all pixels, which take different numbers of samples with adaptive sampling
+    pub(crate) samples_per_pixel: f32,
+}
+
+/// Sums up the noise of every pixel on the GPU and reads the sums back without waiting for them,
+/// so the estimate is a few frames behind the image
+pub(crate) struct Statistics {
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// One sum for every workgroup
+    partial_sums_buffer: wgpu::Buffer,
+    pub(crate) pipeline_layout: wgpu::PipelineLayout,
+    pub(crate) pipeline: wgpu::ComputePipeline,
+    /// The copy in flight
+    readback: Option<Readback>,
+    pub(crate) estimate: Option<NoiseEstimate>,
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    partial_sums_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Statistics Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: partial_sums_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+fn create_partial_sums_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Buffer {
+    let workgroup_count = width.div_ceil(WORKGROUP_SIZE.0) * height.div_ceil(WORKGROUP_SIZE.1);
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Statistics Partial Sums Buffer"),
+        size: workgroup_count as u64 * 16,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+impl Statistics {
+    /// Fails when the shader does not compile
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        main_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        shader_reload: &ShaderReload,
+    ) -> Result<Self, String> {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Statistics Uniform Buffer"),
+            size: GpuStatistics::SHADER_SIZE.get(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Statistics Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(GpuStatistics::SHADER_SIZE),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let partial_sums_buffer = create_partial_sums_buffer(device, 1, 1);
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &partial_sums_buffer,
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Statistics Pipeline Layout"),
+            bind_group_layouts: &[main_texture_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = shader_reload::create_pipeline(
+            device,
+            Shader::Statistics,
+            &shader_reload.source(Shader::Statistics, &[])?,
+            &pipeline_layout,
+        )?;
+
+        Ok(Self {
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            partial_sums_buffer,
+            pipeline_layout,
+            pipeline,
+            readback: None,
+            estimate: None,
+        })
+    }
+
+    pub(crate) fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.partial_sums_buffer = create_partial_sums_buffer(device, width, height);
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.partial_sums_buffer,
+        );
+        // the sums in flight are for the old size
+        self.restart();
+    }
+
+    /// Forgets the sums of the image from before, when it starts accumulating again
+    pub(crate) fn restart(&mut self) {
+        self.readback = None;
+        self.estimate = None;
+    }
+
+    /// Picks up the sums that were read back since the last call, and when `sum_again` starts summing up
+    /// the image again if nothing is in flight anymore
+    pub(crate) fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        main_texture_bind_group: &wgpu::BindGroup,
+        (width, height): (u32, u32),
+        sample_count: u32,
+        sum_again: bool,
+    ) {
+        if let Some(readback) = &self.readback {
+            let Some(result) = readback.try_read(device) else {
+                return;
+            };
+            if let Ok(data) = result {
+                let (relative_error, sample_count, pixel_count) = data
+                    .chunks_exact(16)
+                    .map(|sum| {
+                        let component = |index: usize| {
+                            f32::from_le_bytes(sum[index * 4..index * 4 + 4].try_into().unwrap())
+                        };
+                        (component(0), component(1), component(2))
+                    })
+                    .fold((0.0, 0.0, 0.0), |total, sum| {
+                        (total.0 + sum.0, total.1 + sum.1, total.2 + sum.2)
+                    });
+                self.estimate = (pixel_count > 0.0).then(|| NoiseEstimate {
+                    relative_error: relative_error / pixel_count,
+                    samples_per_pixel: sample_count / pixel_count,
+                });
+            }
+            self.readback = None;
+        }
+        if !sum_again {
+            return;
+        }
+
+        let mut buffer = UniformBuffer::new([0; GpuStatistics::SHADER_SIZE.get() as _]);
+        buffer.write(&GpuStatistics { sample_count }).unwrap();
+        queue.write_buffer(&self.uniform_buffer, 0, &buffer.into_inner());
+
+        let readback = Readback::new(
+            device,
+            queue,
+            self.partial_sums_buffer.size(),
+            |encoder, readback_buffer| {
+                {
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("Statistics Pass"),
+                            timestamp_writes: None,
+                        });
+                    compute_pass.set_pipeline(&self.pipeline);
+                    compute_pass.set_bind_group(0, main_texture_bind_group, &[]);
+                    compute_pass.set_bind_group(1, &self.bind_group, &[]);
+                    compute_pass.dispatch_workgroups(
+                        width.div_ceil(WORKGROUP_SIZE.0),
+                        height.div_ceil(WORKGROUP_SIZE.1),
+                        1,
+                    );
+                }
+                encoder.copy_buffer_to_buffer(
+                    &self.partial_sums_buffer,
+                    0,
+                    readback_buffer,
+                    0,
+                    self.partial_sums_buffer.size(),
+                );
+            },
+        );
+        self.readback = Some(readback);
+    }
+}
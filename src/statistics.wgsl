@@ -0,0 +1,63 @@
+#import material
+
+@group(0)
+@binding(0)
+var texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(0)
+@binding(4)
+var moment_texture: texture_storage_2d<rgba32float, read_write>;
+
+struct Statistics {
+    sample_count: u32,
+}
+
+@group(1)
+@binding(0)
+var<uniform> statistics: Statistics;
+
+// the summed relative standard error in x, sample count in y and number of pixels in z, for every workgroup
+@group(1)
+@binding(1)
+var<storage, read_write> partial_sums: array<vec4<f32>>;
+
+const workgroup_size: u32 = 256u;
+
+var<workgroup> sums: array<vec4<f32>, workgroup_size>;
+
+@compute
+@workgroup_size(16, 16)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) workgroup_count: vec3<u32>,
+) {
+    let size = textureDimensions(texture);
+    let coords = global_id.xy;
+
+    var sum = vec4<f32>(0.0);
+    if coords.x < size.x && coords.y < size.y {
+        let moment = textureLoad(moment_texture, coords).rg;
+        if moment.g > 0.0 {
+            // the same estimate adaptive sampling uses
+            let mean = luminance(textureLoad(texture, coords).rgb);
+            let variance = max(moment.r - mean * mean, 0.0);
+            let frame_count = moment.g / f32(statistics.sample_count);
+            let relative_error = sqrt(variance / frame_count) / max(mean, 0.01);
+            sum = vec4<f32>(relative_error, moment.g, 1.0, 0.0);
+        }
+    }
+    sums[local_index] = sum;
+
+    for (var stride = workgroup_size / 2u; stride > 0u; stride /= 2u) {
+        workgroupBarrier();
+        if local_index < stride {
+            sums[local_index] += sums[local_index + stride];
+        }
+    }
+
+    if local_index == 0u {
+        partial_sums[workgroup_id.x + workgroup_id.y * workgroup_count.x] = sums[0];
+    }
+}
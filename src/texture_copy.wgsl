@@ -6,6 +6,76 @@ var output_texture: texture_storage_2d<rgba8unorm, write>;
 @binding(0)
 var main_texture: texture_storage_2d<rgba32float, read_write>;
 
+@group(2)
+@binding(1)
+var normal_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(2)
+@binding(2)
+var albedo_depth_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(2)
+@binding(3)
+var aov_texture: texture_storage_2d<rgba32float, read_write>;
+
+@group(2)
+@binding(4)
+var moment_texture: texture_storage_2d<rgba32float, read_write>;
+
+const view_mode_beauty: u32 = 0u;
+const view_mode_depth: u32 = 1u;
+const view_mode_normal: u32 = 2u;
+const view_mode_albedo: u32 = 3u;
+const view_mode_object_id: u32 = 4u;
+const view_mode_w_coordinate: u32 = 5u;
+const view_mode_bounce_count: u32 = 6u;
+const view_mode_intersection_test_count: u32 = 7u;
+const view_mode_sample_count: u32 = 8u;
+
+struct View {
+    mode: u32,
+    normal_axes: vec3<u32>,
+    max_depth: f32,
+    w_range: f32,
+    max_bounce_count: f32,
+    max_intersection_test_count: f32,
+    max_sample_count: f32,
+}
+
+@group(3)
+@binding(0)
+var<uniform> view: View;
+
+// non zero for every object index that is selected
+@group(3)
+@binding(1)
+var<storage, read> selected_objects: array<u32>;
+
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let result = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (result >> 22u) ^ result;
+}
+
+// blue for cold through green and yellow to red for hot
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 3.0;
+    return clamp(vec3<f32>(x - 1.0, min(x, 3.0 - x), 1.0 - x), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// the index of the object at `coords` + 1, zero for the sky and outside of the image
+fn object_at(coords: vec2<i32>) -> u32 {
+    let size = vec2<i32>(textureDimensions(aov_texture));
+    if any(coords < vec2<i32>(0)) || any(coords >= size) {
+        return 0u;
+    }
+    return u32(textureLoad(aov_texture, coords).r);
+}
+
+fn is_selected(object: u32) -> bool {
+    return object != 0u && object - 1u < arrayLength(&selected_objects) && selected_objects[object - 1u] != 0u;
+}
+
 @compute
 @workgroup_size(16, 16)
 fn main(
@@ -18,6 +88,69 @@ fn main(
         return;
     }
 
-    let color = textureLoad(main_texture, coords);
-    textureStore(output_texture, coords, color);
+    var color: vec3<f32>;
+    switch view.mode {
+        case view_mode_depth: {
+            let depth = textureLoad(albedo_depth_texture, coords).a;
+            if depth > 0.0 {
+                color = vec3<f32>(1.0 - depth / view.max_depth);
+            } else {
+                color = vec3<f32>(0.0);
+            }
+        }
+        case view_mode_normal: {
+            let normal = textureLoad(normal_texture, coords);
+            color = vec3<f32>(normal[view.normal_axes.x], normal[view.normal_axes.y], normal[view.normal_axes.z]) * 0.5 + 0.5;
+        }
+        case view_mode_albedo: {
+            color = textureLoad(albedo_depth_texture, coords).rgb;
+        }
+        case view_mode_object_id: {
+            let id = u32(textureLoad(aov_texture, coords).r);
+            if id == 0u {
+                color = vec3<f32>(0.0);
+            } else {
+                let h = hash(id);
+                color = vec3<f32>(f32(h & 0xffu), f32((h >> 8u) & 0xffu), f32((h >> 16u) & 0xffu)) / 255.0;
+            }
+        }
+        case view_mode_w_coordinate: {
+            let aov = textureLoad(aov_texture, coords);
+            let t = aov.g / view.w_range;
+            // red for positive w, blue for negative w
+            color = vec3<f32>(max(t, 0.0), 0.0, max(-t, 0.0));
+            if aov.r == 0.0 {
+                color = vec3<f32>(0.0);
+            }
+        }
+        case view_mode_bounce_count: {
+            color = heatmap(textureLoad(aov_texture, coords).b / view.max_bounce_count);
+        }
+        case view_mode_intersection_test_count: {
+            color = heatmap(textureLoad(aov_texture, coords).a / view.max_intersection_test_count);
+        }
+        case view_mode_sample_count: {
+            color = heatmap(textureLoad(moment_texture, coords).g / view.max_sample_count);
+        }
+        default: {
+            color = textureLoad(main_texture, coords).rgb;
+        }
+    }
+    color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+
+    // outlines the selected objects where they border anything else
+    let object = object_at(vec2<i32>(coords));
+    if is_selected(object) {
+        let outline = object_at(vec2<i32>(coords) + vec2<i32>(1, 0)) != object
+            || object_at(vec2<i32>(coords) - vec2<i32>(1, 0)) != object
+            || object_at(vec2<i32>(coords) + vec2<i32>(0, 1)) != object
+            || object_at(vec2<i32>(coords) - vec2<i32>(0, 1)) != object;
+        if outline {
+            color = vec3<f32>(1.0, 0.6, 0.0);
+        } else {
+            color = mix(color, vec3<f32>(1.0, 0.6, 0.0), 0.1);
+        }
+    }
+
+    textureStore(output_texture, coords, vec4<f32>(color, 1.0));
 }
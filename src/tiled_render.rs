@@ -0,0 +1,342 @@
+use crate::readback::Readback;
+use eframe::wgpu;
+use std::{fs::File, io::BufWriter, io::Write};
+
+pub(crate) struct TiledRenderSettings {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) tile_size: u32,
+    pub(crate) samples_per_pixel: u32,
+    pub(crate) path: String,
+}
+
+impl Default for TiledRenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 7680,
+            height: 4320,
+            tile_size: 512,
+            samples_per_pixel: 256,
+            path: "render.png".into(),
+        }
+    }
+}
+
+/// The part of the image a single dispatch renders, in texture coordinates where y points up
+#[derive(Clone, Copy)]
+pub(crate) struct Tile {
+    pub(crate) offset: cgmath::Vector2<u32>,
+    pub(crate) size: cgmath::Vector2<u32>,
+}
+
+/// Everything a tile is rendered with that is shared with the viewport
+pub(crate) struct TilePipelines<'a> {
+    pub(crate) raytracing_pipeline: &'a wgpu::ComputePipeline,
+    pub(crate) camera_bind_group: &'a wgpu::BindGroup,
+    pub(crate) scene_bind_group: &'a wgpu::BindGroup,
+    pub(crate) texture_copy_pipeline: &'a wgpu::ComputePipeline,
+}
+
+/// Renders an image of any size one tile at a time, streaming every finished row of tiles into a png,
+/// so neither the texture size limit nor the memory needed for the whole image get in the way
+pub(crate) struct TiledRender {
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    frames_per_tile: u32,
+    main_texture_bind_group: wgpu::BindGroup,
+    display_texture_bind_group: wgpu::BindGroup,
+    output_texture: wgpu::Texture,
+    output_texture_bind_group: wgpu::BindGroup,
+    /// Always shows the image itself, whatever the viewport shows
+    view_bind_group: wgpu::BindGroup,
+    /// The copy of the last finished tile, nothing gets rendered until it is back
+    readback: Option<Readback>,
+    /// A tile row padded to the alignment buffer copies need
+    padded_bytes_per_row: u32,
+    path: String,
+    writer: png::StreamWriter<'static, BufWriter<File>>,
+    /// The pixels of the current row of tiles, top row first like the png expects
+    strip: Vec<u8>,
+    /// Counting rows of tiles from the top of the image
+    tile_row: u32,
+    tile_column: u32,
+    frame_count: u32,
+}
+
+impl TiledRender {
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        main_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        display_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        output_texture_bind_group_layout: &wgpu::BindGroupLayout,
+        view_bind_group: wgpu::BindGroup,
+        settings: &TiledRenderSettings,
+        camera_sample_count: u32,
+    ) -> anyhow::Result<Self> {
+        let TiledRenderSettings {
+            width,
+            height,
+            tile_size,
+            samples_per_pixel,
+            ref path,
+        } = *settings;
+        anyhow::ensure!(width > 0 && height > 0, "the image is empty");
+        let tile_size = tile_size.clamp(1, device.limits().max_texture_dimension_2d);
+
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let writer = encoder
+            .write_header()
+            .and_then(png::Writer::into_stream_writer)
+            .inspect_err(|_| {
+                let _ = std::fs::remove_file(path);
+            })?;
+
+        let tile_extent = wgpu::Extent3d {
+            width: tile_size,
+            height: tile_size,
+            depth_or_array_layers: 1,
+        };
+        let main_texture_views = ["Main", "Normal", "Albedo Depth", "AOV", "Moment"].map(|name| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(&format!("Tiled Render {name} Texture")),
+                    size: tile_extent,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    usage: wgpu::TextureUsages::STORAGE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let main_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tiled Render Main Texture Bind Group"),
+            layout: main_texture_bind_group_layout,
+            entries: &main_texture_views
+                .iter()
+                .enumerate()
+                .map(|(binding, view)| wgpu::BindGroupEntry {
+                    binding: binding as _,
+                    resource: wgpu::BindingResource::TextureView(view),
+                })
+                .collect::<Vec<_>>(),
+        });
+        let display_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tiled Render Display Texture Bind Group"),
+            layout: display_texture_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&main_texture_views[0]),
+            }],
+        });
+
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Tiled Render Output Texture"),
+            size: tile_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tiled Render Output Texture Bind Group"),
+            layout: output_texture_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &output_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            }],
+        });
+
+        let padded_bytes_per_row =
+            (tile_size * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        Ok(Self {
+            width,
+            height,
+            tile_size,
+            frames_per_tile: samples_per_pixel.div_ceil(camera_sample_count).max(1),
+            main_texture_bind_group,
+            display_texture_bind_group,
+            output_texture,
+            output_texture_bind_group,
+            view_bind_group,
+            readback: None,
+            padded_bytes_per_row,
+            path: path.clone(),
+            writer,
+            strip: vec![0; width as usize * tile_size.min(height) as usize * 4],
+            tile_row: 0,
+            tile_column: 0,
+            frame_count: 0,
+        })
+    }
+
+    fn tile_row_count(&self) -> u32 {
+        self.height.div_ceil(self.tile_size)
+    }
+
+    fn tile_column_count(&self) -> u32 {
+        self.width.div_ceil(self.tile_size)
+    }
+
+    pub(crate) fn image_size(&self) -> cgmath::Vector2<u32> {
+        cgmath::vec2(self.width, self.height)
+    }
+
+    /// The tile that is currently being rendered
+    pub(crate) fn tile(&self) -> Tile {
+        // the png starts at the top, but y points up in the textures
+        let top = self.height - self.tile_row * self.tile_size;
+        let bottom = top.saturating_sub(self.tile_size);
+        let left = self.tile_column * self.tile_size;
+        let right = (left + self.tile_size).min(self.width);
+        Tile {
+            offset: cgmath::vec2(left, bottom),
+            size: cgmath::vec2(right - left, top - bottom),
+        }
+    }
+
+    /// The frame of the current tile that gets rendered next
+    pub(crate) fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// How much of the image is done, from 0 to 1
+    pub(crate) fn progress(&self) -> f32 {
+        let tile_index = self.tile_row * self.tile_column_count() + self.tile_column;
+        let frame_index = tile_index * self.frames_per_tile + self.frame_count;
+        frame_index as f32
+            / (self.tile_row_count() * self.tile_column_count() * self.frames_per_tile) as f32
+    }
+
+    /// Renders the next frame of the current tile, the camera uniform must already be written for it.
+    /// Once a tile is finished this waits for its copy over the next calls instead of rendering.
+    /// Returns whether the image is finished and saved
+    pub(crate) fn render_frame(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &TilePipelines<'_>,
+    ) -> anyhow::Result<bool> {
+        let tile = self.tile();
+        if let Some(readback) = &self.readback {
+            let Some(data) = readback.try_read(device) else {
+                return Ok(false);
+            };
+            self.readback = None;
+            return self.finish_tile(tile, &data?);
+        }
+
+        let last_frame = self.frame_count + 1 == self.frames_per_tile;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Tiled Render Encoder"),
+        });
+        {
+            let workgroup_size = (16, 16);
+            let (dispatch_width, dispatch_height) = (
+                tile.size.x.div_ceil(workgroup_size.0),
+                tile.size.y.div_ceil(workgroup_size.1),
+            );
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Tiled Render Compute Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(pipelines.raytracing_pipeline);
+            compute_pass.set_bind_group(0, &self.main_texture_bind_group, &[]);
+            compute_pass.set_bind_group(1, pipelines.camera_bind_group, &[]);
+            compute_pass.set_bind_group(2, pipelines.scene_bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+
+            if last_frame {
+                compute_pass.set_pipeline(pipelines.texture_copy_pipeline);
+                compute_pass.set_bind_group(0, &self.output_texture_bind_group, &[]);
+                compute_pass.set_bind_group(1, &self.display_texture_bind_group, &[]);
+                compute_pass.set_bind_group(2, &self.main_texture_bind_group, &[]);
+                compute_pass.set_bind_group(3, &self.view_bind_group, &[]);
+                compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+            }
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.frame_count += 1;
+        if last_frame {
+            self.readback = Some(Readback::new(
+                device,
+                queue,
+                self.padded_bytes_per_row as u64 * tile.size.y as u64,
+                |encoder, buffer| {
+                    encoder.copy_texture_to_buffer(
+                        self.output_texture.as_image_copy(),
+                        wgpu::ImageCopyBuffer {
+                            buffer,
+                            layout: wgpu::ImageDataLayout {
+                                offset: 0,
+                                bytes_per_row: Some(self.padded_bytes_per_row),
+                                rows_per_image: None,
+                            },
+                        },
+                        wgpu::Extent3d {
+                            width: tile.size.x,
+                            height: tile.size.y,
+                            depth_or_array_layers: 1,
+                        },
+                    );
+                },
+            ));
+        }
+        Ok(false)
+    }
+
+    /// Puts the pixels of the tile that was read back into the strip, and writes the strip once the
+    /// row of tiles is complete. Returns whether that was the last one
+    fn finish_tile(&mut self, tile: Tile, data: &[u8]) -> anyhow::Result<bool> {
+        let row_len = tile.size.x as usize * 4;
+        for y in 0..tile.size.y as usize {
+            // flipped, since the strip starts at the top of the tile
+            let source = y * self.padded_bytes_per_row as usize;
+            let destination =
+                ((tile.size.y as usize - 1 - y) * self.width as usize + tile.offset.x as usize) * 4;
+            self.strip[destination..destination + row_len]
+                .copy_from_slice(&data[source..source + row_len]);
+        }
+
+        self.frame_count = 0;
+        self.tile_column += 1;
+        if self.tile_column < self.tile_column_count() {
+            return Ok(false);
+        }
+
+        let strip_len = self.width as usize * tile.size.y as usize * 4;
+        self.writer.write_all(&self.strip[..strip_len])?;
+        self.tile_column = 0;
+        self.tile_row += 1;
+        Ok(self.tile_row == self.tile_row_count())
+    }
+
+    /// Writes the end of the png, must be called once `render_frame` returns true. The file is deleted
+    /// when that fails
+    pub(crate) fn finish(self) -> anyhow::Result<()> {
+        let result = self.writer.finish();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        Ok(result?)
+    }
+
+    /// Stops rendering and deletes the unfinished png
+    pub(crate) fn cancel(self) -> anyhow::Result<()> {
+        // closed first, since open files cannot be deleted everywhere
+        drop(self.writer);
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}